@@ -0,0 +1,305 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use console::style;
+
+use crate::schemas::system::{BuildConfig, IgnorableErrorBehavior, IntentPkg, IntentPkgOpt, SchemaParsingError};
+
+// Shipped alongside the binary; individual recipes are rendered from this per package.
+const DEFAULT_RECIPE_TEMPLATE: &str = include_str!("../templates/makepkg.dockerfile.tmpl");
+
+#[derive(Debug)]
+pub enum BuildError {
+    CommandFailed { backend: &'static str, problem: String },
+    NoArtifactsProduced,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::CommandFailed { backend, problem } => {
+                write!(f, "{} build failed: {}", backend, problem)
+            }
+            BuildError::NoArtifactsProduced => {
+                write!(f, "build succeeded but produced no *.pkg.tar.* artifacts")
+            }
+        }
+    }
+}
+
+// A Dockerfile-like recipe with `{{ image }}`, `{{ pkg }}`, and `{{ flags }}` substitution
+// tokens, rendered once per package before being handed to a `BuildBackend`.
+pub struct RecipeTemplate {
+    source: String,
+}
+
+impl RecipeTemplate {
+    pub fn from_source(source: impl Into<String>) -> Self {
+        RecipeTemplate { source: source.into() }
+    }
+
+    pub fn render(&self, image: &str, pkg: &IntentPkg) -> String {
+        self.source
+            .replace("{{ image }}", image)
+            .replace("{{ pkg }}", &pkg.name)
+            .replace("{{ flags }}", &assemble_flags(&pkg.opts))
+    }
+}
+
+impl Default for RecipeTemplate {
+    fn default() -> Self {
+        RecipeTemplate::from_source(DEFAULT_RECIPE_TEMPLATE)
+    }
+}
+
+// Turns `IntentPkg.opts` into makepkg-style CLI flags, e.g. a boolean opt named `nocheck` set to
+// `true` becomes `--nocheck`. Sorted so repeated builds render identical recipes.
+fn assemble_flags(opts: &Option<HashMap<String, IntentPkgOpt>>) -> String {
+    let opts = match opts {
+        Some(opts) => opts,
+        None => return String::new(),
+    };
+
+    let mut flags: Vec<String> = opts
+        .iter()
+        .filter_map(|(name, value)| match value {
+            IntentPkgOpt::Boolean(true) => Some(format!("--{}", name)),
+            IntentPkgOpt::Boolean(false) | IntentPkgOpt::Null => None,
+            IntentPkgOpt::String(value) => Some(format!("--{}={}", name, value)),
+            IntentPkgOpt::Number(value) => Some(format!("--{}={}", name, value)),
+            IntentPkgOpt::List(_) | IntentPkgOpt::Map(_) => Some(format!("--{}", name)),
+        })
+        .collect();
+
+    flags.sort();
+    flags.join(" ")
+}
+
+// Pluggable container builder: ships a recipe, builds it, and extracts `/out` from the result.
+// `ContainerBackend::docker()`/`ContainerBackend::podman()` below are the stock implementations;
+// anything else that can build an image
+// and `cp` files out of a container can implement this trait too.
+pub trait BuildBackend {
+    fn name(&self) -> &'static str;
+
+    fn build(&self, recipe: &str, context: &Path, out_dir: &Path) -> Result<(), BuildError>;
+}
+
+pub struct ContainerBackend {
+    executable: &'static str,
+}
+
+impl ContainerBackend {
+    pub fn docker() -> Self {
+        ContainerBackend { executable: "docker" }
+    }
+
+    pub fn podman() -> Self {
+        ContainerBackend { executable: "podman" }
+    }
+}
+
+impl BuildBackend for ContainerBackend {
+    fn name(&self) -> &'static str {
+        self.executable
+    }
+
+    fn build(&self, recipe: &str, context: &Path, out_dir: &Path) -> Result<(), BuildError> {
+        let recipe_path = context.join(format!(".oof-recipe-{}.dockerfile", next_id()));
+        std::fs::write(&recipe_path, recipe).map_err(|err| BuildError::CommandFailed {
+            backend: self.executable,
+            problem: format!("could not write rendered recipe: {}", err),
+        })?;
+
+        let tag = format!("oof-build-{}", next_id());
+        let build_result = self.run(
+            Command::new(self.executable)
+                .arg("build")
+                .arg("-f")
+                .arg(&recipe_path)
+                .arg("-t")
+                .arg(&tag)
+                .arg(context),
+        );
+
+        let _ = std::fs::remove_file(&recipe_path);
+        build_result?;
+
+        let container = format!("{}-extract", tag);
+        self.run(
+            Command::new(self.executable)
+                .arg("create")
+                .arg("--name")
+                .arg(&container)
+                .arg(&tag),
+        )?;
+
+        std::fs::create_dir_all(out_dir).map_err(|err| BuildError::CommandFailed {
+            backend: self.executable,
+            problem: format!("could not create output directory: {}", err),
+        })?;
+
+        let copy_result = self.run(Command::new(self.executable).arg("cp").arg(format!("{}:/out/.", container)).arg(out_dir));
+
+        let _ = Command::new(self.executable).arg("rm").arg("-f").arg(&container).status();
+        let _ = Command::new(self.executable).arg("rmi").arg("-f").arg(&tag).status();
+
+        copy_result
+    }
+}
+
+impl ContainerBackend {
+    fn run(&self, command: &mut Command) -> Result<(), BuildError> {
+        match command.status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(BuildError::CommandFailed {
+                backend: self.executable,
+                problem: format!("exited with {}", status),
+            }),
+            Err(err) => Err(BuildError::CommandFailed {
+                backend: self.executable,
+                problem: err.to_string(),
+            }),
+        }
+    }
+}
+
+fn next_id() -> usize {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+// `out_dir` is shared by every package in a `build_all` run, so a plain glob after each build
+// would re-return every prior package's artifacts too. `build_one` snapshots the directory
+// before building and passes it here so only files that are new since then come back.
+fn snapshot_artifacts(out_dir: &Path) -> HashSet<PathBuf> {
+    std::fs::read_dir(out_dir)
+        .map(|entries| entries.filter_map(Result::ok).map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+fn glob_new_artifacts(out_dir: &Path, before: &HashSet<PathBuf>) -> Result<Vec<PathBuf>, BuildError> {
+    let entries = std::fs::read_dir(out_dir).map_err(|err| BuildError::CommandFailed {
+        backend: "oof",
+        problem: format!("could not read output directory: {}", err),
+    })?;
+
+    let artifacts: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| !before.contains(path))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.contains(".pkg.tar."))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    if artifacts.is_empty() {
+        return Err(BuildError::NoArtifactsProduced);
+    }
+
+    Ok(artifacts)
+}
+
+// Builds every `IntentPkg` and plain `rawpkgs` entry (treated as a package with no opts),
+// routing individual failures through `behavior` instead of aborting the whole run outright.
+pub fn build_all(
+    pkgs: &[IntentPkg],
+    rawpkgs: &[String],
+    recipe: &RecipeTemplate,
+    config: &BuildConfig,
+    sources_dir: &Path,
+    backend: &dyn BuildBackend,
+    behavior: &IgnorableErrorBehavior,
+) -> Result<Vec<PathBuf>, SchemaParsingError> {
+    let mut artifacts = Vec::new();
+
+    let raw_as_intent: Vec<IntentPkg> = rawpkgs
+        .iter()
+        .map(|name| IntentPkg { name: name.clone(), opts: None })
+        .collect();
+
+    for pkg in pkgs.iter().chain(raw_as_intent.iter()) {
+        match build_one(pkg, recipe, config, sources_dir, backend) {
+            Ok(mut built) => artifacts.append(&mut built),
+            Err(err) => match behavior {
+                IgnorableErrorBehavior::Error => {
+                    return Err(SchemaParsingError::BuildFailed {
+                        package: pkg.name.clone(),
+                        problem: err.to_string(),
+                    });
+                }
+                IgnorableErrorBehavior::Warn => {
+                    eprintln!("{} building {}: {}", style("warning").yellow(), pkg.name, err);
+                }
+                IgnorableErrorBehavior::Ignore => {}
+            },
+        }
+    }
+
+    Ok(artifacts)
+}
+
+fn build_one(
+    pkg: &IntentPkg,
+    recipe: &RecipeTemplate,
+    config: &BuildConfig,
+    sources_dir: &Path,
+    backend: &dyn BuildBackend,
+) -> Result<Vec<PathBuf>, BuildError> {
+    let before = snapshot_artifacts(&config.output_dir);
+
+    let rendered = recipe.render(&config.image, pkg);
+    backend.build(&rendered, sources_dir, &config.output_dir)?;
+
+    glob_new_artifacts(&config.output_dir, &before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_flags_handles_no_opts() {
+        assert_eq!(assemble_flags(&None), "");
+    }
+
+    #[test]
+    fn assemble_flags_renders_a_true_boolean_as_a_bare_flag() {
+        let mut opts = HashMap::new();
+        opts.insert("nocheck".to_string(), IntentPkgOpt::Boolean(true));
+        assert_eq!(assemble_flags(&Some(opts)), "--nocheck");
+    }
+
+    #[test]
+    fn assemble_flags_drops_a_false_boolean_and_a_null() {
+        let mut opts = HashMap::new();
+        opts.insert("nocheck".to_string(), IntentPkgOpt::Boolean(false));
+        opts.insert("debug".to_string(), IntentPkgOpt::Null);
+        assert_eq!(assemble_flags(&Some(opts)), "");
+    }
+
+    #[test]
+    fn assemble_flags_renders_strings_and_numbers_as_key_value_pairs() {
+        let mut opts = HashMap::new();
+        opts.insert("version".to_string(), IntentPkgOpt::Number(5));
+        opts.insert("arch".to_string(), IntentPkgOpt::String("x86_64".to_string()));
+        assert_eq!(assemble_flags(&Some(opts)), "--arch=x86_64 --version=5");
+    }
+
+    #[test]
+    fn assemble_flags_sorts_output_for_stable_renders() {
+        let mut opts = HashMap::new();
+        opts.insert("zzz".to_string(), IntentPkgOpt::Boolean(true));
+        opts.insert("aaa".to_string(), IntentPkgOpt::Boolean(true));
+        assert_eq!(assemble_flags(&Some(opts)), "--aaa --zzz");
+    }
+}
@@ -0,0 +1,265 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use clap::{App, AppSettings, Arg, SubCommand};
+use over::obj::Obj;
+
+const KNOWN_SUBCOMMANDS: &[&str] = &["check", "plan", "apply"];
+
+#[derive(Debug)]
+pub enum Command {
+    Check { file: PathBuf },
+    Plan { file: PathBuf },
+    Apply { file: PathBuf },
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownSubcommand(String),
+    MalformedAlias { name: String, problem: &'static str },
+}
+
+// `alias.<name>` entries, normalized to argv form. A value may be written as a single string
+// ("check --offline", split on whitespace) or as an explicit array (`["check", "--offline"]`);
+// either way this map only ever holds the latter.
+pub type AliasMap = HashMap<String, Vec<String>>;
+
+pub fn parse_aliases(config: &Obj) -> Result<AliasMap, CliError> {
+    let alias_obj = match config.get_obj(&"alias") {
+        Ok(alias) => alias,
+        Err(_) => return Ok(AliasMap::new()),
+    };
+
+    let mut aliases = AliasMap::with_capacity(alias_obj.len());
+
+    for (name, value) in alias_obj.iter() {
+        if let Ok(argv) = value.get_str() {
+            aliases.insert(name.clone(), argv.split_whitespace().map(String::from).collect());
+            continue;
+        }
+
+        if let Ok(argv) = value.get_arr() {
+            let argv: Result<Vec<String>, _> = argv.vec_ref().iter().map(|item| item.get_str()).collect();
+            match argv {
+                Ok(argv) => {
+                    aliases.insert(name.clone(), argv);
+                    continue;
+                }
+                Err(_) => {
+                    return Err(CliError::MalformedAlias {
+                        name: name.clone(),
+                        problem: "array entries must all be strings",
+                    });
+                }
+            }
+        }
+
+        return Err(CliError::MalformedAlias {
+            name: name.clone(),
+            problem: "must be a string or an array of strings",
+        });
+    }
+
+    Ok(aliases)
+}
+
+// Expands `argv[0]` through `aliases` when it isn't already one of our built-in subcommands,
+// mirroring cargo: `oof co system.over.oof` with `alias.co = "check"` becomes
+// `oof check system.over.oof`.
+fn expand_aliases(argv: Vec<String>, aliases: &AliasMap) -> Vec<String> {
+    let alias_expansion = match argv.first() {
+        Some(first) if !KNOWN_SUBCOMMANDS.contains(&first.as_str()) => aliases.get(first),
+        _ => None,
+    };
+
+    match alias_expansion {
+        Some(expansion) => {
+            let mut expanded = expansion.clone();
+            expanded.extend(argv.into_iter().skip(1));
+            expanded
+        }
+        None => argv,
+    }
+}
+
+fn build_app() -> App<'static, 'static> {
+    let file_arg = Arg::with_name("file")
+        .help("path to the .over.oof document to operate on")
+        .required(true);
+
+    App::new("oof")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("parse and validate a document, fetching and merging its extends chain, without applying it")
+                .arg(file_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("resolve extends/using and show what would change")
+                .arg(file_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("resolve and execute a document against its target")
+                .arg(file_arg),
+        )
+}
+
+pub fn parse(argv: Vec<String>, aliases: &AliasMap) -> Result<Command, CliError> {
+    let argv = expand_aliases(argv, aliases);
+
+    let matches = build_app()
+        .get_matches_from_safe(std::iter::once("oof".to_string()).chain(argv))
+        .map_err(|err| CliError::UnknownSubcommand(err.to_string()))?;
+
+    match matches.subcommand() {
+        ("check", Some(sub)) => Ok(Command::Check { file: file_arg(sub) }),
+        ("plan", Some(sub)) => Ok(Command::Plan { file: file_arg(sub) }),
+        ("apply", Some(sub)) => Ok(Command::Apply { file: file_arg(sub) }),
+        (other, _) => Err(CliError::UnknownSubcommand(other.to_string())),
+    }
+}
+
+fn file_arg(sub: &clap::ArgMatches) -> PathBuf {
+    PathBuf::from(sub.value_of("file").expect("file is a required argument"))
+}
+
+// Aliases are normally resolved from a user-level config file (cargo-style: `.cargo/config.toml`
+// plays this role for cargo), at `$XDG_CONFIG_HOME/oof/config.over` by default. A malformed or
+// missing config file just means no aliases are available, rather than a hard failure.
+pub fn load_config_aliases(path: &std::path::Path) -> AliasMap {
+    let obj = match Obj::from_file(path.to_string_lossy().as_ref()) {
+        Ok(obj) => obj,
+        Err(_) => return AliasMap::new(),
+    };
+
+    parse_aliases(&obj).unwrap_or_default()
+}
+
+pub fn default_config_path() -> PathBuf {
+    let mut path = config_home();
+    path.push("oof");
+    path.push("config.over");
+    path
+}
+
+fn config_home() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(pairs: &[(&str, &[&str])]) -> AliasMap {
+        pairs
+            .iter()
+            .map(|(name, argv)| (name.to_string(), argv.iter().map(|s| s.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn expand_aliases_leaves_a_known_subcommand_untouched() {
+        let aliases = aliases(&[("check", &["plan"])]);
+        let argv = vec!["check".to_string(), "system.over.oof".to_string()];
+        assert_eq!(expand_aliases(argv.clone(), &aliases), argv);
+    }
+
+    #[test]
+    fn expand_aliases_expands_a_single_word_alias() {
+        let aliases = aliases(&[("co", &["check"])]);
+        let argv = vec!["co".to_string(), "system.over.oof".to_string()];
+        assert_eq!(
+            expand_aliases(argv, &aliases),
+            vec!["check".to_string(), "system.over.oof".to_string()]
+        );
+    }
+
+    #[test]
+    fn expand_aliases_expands_a_multi_word_alias_and_keeps_trailing_args() {
+        let aliases = aliases(&[("co", &["check", "--offline"])]);
+        let argv = vec!["co".to_string(), "system.over.oof".to_string()];
+        assert_eq!(
+            expand_aliases(argv, &aliases),
+            vec!["check".to_string(), "--offline".to_string(), "system.over.oof".to_string()]
+        );
+    }
+
+    #[test]
+    fn expand_aliases_leaves_an_unknown_first_argument_untouched_if_no_alias_matches() {
+        let aliases = AliasMap::new();
+        let argv = vec!["nonexistent".to_string()];
+        assert_eq!(expand_aliases(argv.clone(), &aliases), argv);
+    }
+
+    #[test]
+    fn expand_aliases_handles_an_empty_argv() {
+        let aliases = aliases(&[("co", &["check"])]);
+        assert_eq!(expand_aliases(Vec::new(), &aliases), Vec::<String>::new());
+    }
+
+    #[test]
+    fn parse_aliases_splits_a_string_value_on_whitespace() {
+        let mut alias_map = HashMap::new();
+        alias_map.insert("co".to_string(), over::value::Value::Str("check --offline".to_string()));
+        let alias_obj = Obj::from_map_unchecked(alias_map);
+
+        let mut obj_map = HashMap::new();
+        obj_map.insert("alias".to_string(), over::value::Value::Obj(alias_obj));
+        let obj = Obj::from_map_unchecked(obj_map);
+
+        let aliases = parse_aliases(&obj).unwrap();
+        assert_eq!(aliases.get("co"), Some(&vec!["check".to_string(), "--offline".to_string()]));
+    }
+
+    #[test]
+    fn parse_aliases_accepts_an_explicit_array_value() {
+        let argv = over::arr::Arr::from_vec(vec![
+            over::value::Value::Str("check".to_string()),
+            over::value::Value::Str("--offline".to_string()),
+        ])
+        .unwrap();
+
+        let mut alias_map = HashMap::new();
+        alias_map.insert("co".to_string(), over::value::Value::Arr(argv));
+        let alias_obj = Obj::from_map_unchecked(alias_map);
+
+        let mut obj_map = HashMap::new();
+        obj_map.insert("alias".to_string(), over::value::Value::Obj(alias_obj));
+        let obj = Obj::from_map_unchecked(obj_map);
+
+        let aliases = parse_aliases(&obj).unwrap();
+        assert_eq!(aliases.get("co"), Some(&vec!["check".to_string(), "--offline".to_string()]));
+    }
+
+    #[test]
+    fn parse_aliases_rejects_an_array_with_a_non_string_entry() {
+        let argv = over::arr::Arr::from_vec_unchecked(
+            vec![over::value::Value::Str("check".to_string()), over::value::Value::Int(1.into())],
+            over::types::Type::Any,
+        );
+
+        let mut alias_map = HashMap::new();
+        alias_map.insert("co".to_string(), over::value::Value::Arr(argv));
+        let alias_obj = Obj::from_map_unchecked(alias_map);
+
+        let mut obj_map = HashMap::new();
+        obj_map.insert("alias".to_string(), over::value::Value::Obj(alias_obj));
+        let obj = Obj::from_map_unchecked(obj_map);
+
+        assert!(matches!(parse_aliases(&obj), Err(CliError::MalformedAlias { .. })));
+    }
+
+    #[test]
+    fn parse_aliases_returns_an_empty_map_when_alias_is_absent() {
+        let obj = Obj::from_map_unchecked(HashMap::new());
+        assert_eq!(parse_aliases(&obj).unwrap(), AliasMap::new());
+    }
+}
@@ -1,24 +1,146 @@
 // This file is part of the OOF project, released under the Creative Commons CC0
 // https://creativecommons.org/publicdomain/zero/1.0/
 
+extern crate clap;
 extern crate console;
 extern crate over;
 extern crate semver;
 
+use std::collections::HashMap;
+
 use console::style;
 use over::obj::Obj;
 
+mod build;
+mod cli;
 mod schemas;
+mod secrets;
+mod target;
+
+use schemas::system::{IgnorableErrorBehavior, OofFile};
+use secrets::{Secret, SecretResolver};
 
 fn main() {
-    let obj = Obj::from_file("examples/alpine/system.over.oof").unwrap();
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let aliases = cli::load_config_aliases(&cli::default_config_path());
 
-    match schemas::system::from_over_obj(&obj) {
-        Ok(cfg) => {
-            println!("{:?}", cfg)
+    let command = match cli::parse(argv, &aliases) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}: {:?}", style("error parsing arguments").red(), err);
+            std::process::exit(1);
         }
+    };
+
+    let file = match &command {
+        cli::Command::Check { file } => file,
+        cli::Command::Plan { file } => file,
+        cli::Command::Apply { file } => file,
+    };
+
+    let obj = match Obj::from_file(file.to_string_lossy().as_ref()) {
+        Ok(obj) => obj,
         Err(err) => {
-            eprintln!("{}: {:?}", style("error parsing <file>").red(), err)
+            eprintln!("{}: {:?}", style("error reading file").red(), err);
+            std::process::exit(1);
         }
+    };
+
+    match schemas::system::from_over_obj(&obj) {
+        Ok(cfg) => match command {
+            cli::Command::Check { .. } => {
+                println!("{} {:?}", style("ok:").green(), cfg);
+            }
+            cli::Command::Plan { .. } => {
+                println!("{} {:?}", style("plan:").green(), cfg.system);
+            }
+            cli::Command::Apply { .. } => {
+                if let Err(err) = apply(&cfg) {
+                    eprintln!("{}: {}", style("error applying").red(), err);
+                    std::process::exit(1);
+                }
+                println!("{} apply finished", style("ok:").green());
+            }
+        },
+        Err(err) => {
+            eprintln!("{}: {:?}", style("error parsing <file>").red(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Resolves every `users.*.password` up front, then drives disks, groups, users, and package
+// installation against whatever `target` the document names, in that order: disks need to exist
+// before packages that expect them are installed onto the running system, and the users/groups
+// that own package files need to exist before `install_packages` runs.
+fn apply(cfg: &OofFile) -> Result<(), String> {
+    let system = &cfg.system;
+
+    let resolver = SecretResolver::new(None);
+    let passwords = resolve_user_passwords(&resolver, &cfg.system)?;
+
+    let backend = target::backend_for(&system.target).map_err(|err| err.to_string())?;
+
+    if let Some(disks) = &system.disks {
+        backend.ensure_disks(disks).map_err(|err| err.to_string())?;
     }
+
+    if let Some(groups) = &system.groups {
+        backend.ensure_groups(groups).map_err(|err| err.to_string())?;
+    }
+
+    if let Some(users) = &system.users {
+        backend.ensure_users(users, &passwords).map_err(|err| err.to_string())?;
+    }
+
+    let artifacts = match &cfg.meta.build {
+        Some(build_config) => {
+            let container = build::ContainerBackend::docker();
+            let recipe = build::RecipeTemplate::default();
+            let sources_dir = std::env::current_dir().map_err(|err| err.to_string())?;
+
+            build::build_all(
+                system.intentpkgs.as_deref().unwrap_or_default(),
+                system.rawpkgs.as_deref().unwrap_or_default(),
+                &recipe,
+                build_config,
+                &sources_dir,
+                &container,
+                &IgnorableErrorBehavior::Error,
+            )
+            .map_err(|err| format!("{:?}", err))?
+        }
+        None => Vec::new(),
+    };
+
+    backend.install_packages(&artifacts).map_err(|err| err.to_string())
+}
+
+fn resolve_user_passwords(
+    resolver: &SecretResolver,
+    system: &schemas::system::SystemSchema20210801,
+) -> Result<HashMap<String, Secret>, String> {
+    let mut passwords = HashMap::new();
+
+    let users = match &system.users {
+        Some(users) => users,
+        None => return Ok(passwords),
+    };
+
+    for (key, user) in users {
+        let password = match &user.password {
+            Some(password) => password,
+            None => continue,
+        };
+
+        let field_name = format!("users.{}.password", key);
+        let secret = secrets::resolve_or_handle(resolver, password, &field_name, &user.not_matched_error_behavior)
+            .map_err(|err| format!("{:?}", err))?;
+
+        if let Some(secret) = secret {
+            passwords.insert(user.name.clone(), secret);
+        }
+    }
+
+    Ok(passwords)
 }
@@ -0,0 +1,307 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+// A deliberately non-exhaustive set of SPDX license identifiers: the ones likely to actually
+// show up in a `meta.license` field. Extend this list as real documents hit ids not covered
+// here, rather than trying to vendor the full SPDX license list up front.
+const KNOWN_SPDX_IDENTIFIERS: &[&str] = &[
+    "0BSD",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSL-1.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "EPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ISC",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "MIT",
+    "MPL-2.0",
+    "NCSA",
+    "OpenSSL",
+    "PostgreSQL",
+    "Python-2.0",
+    "Ruby",
+    "Unlicense",
+    "Vim",
+    "WTFPL",
+    "Zlib",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseExpression {
+    Id(String),
+    And(Box<LicenseExpression>, Box<LicenseExpression>),
+    Or(Box<LicenseExpression>, Box<LicenseExpression>),
+    With(Box<LicenseExpression>, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseParseError {
+    UnknownIdentifier(String),
+    Malformed(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    And,
+    Or,
+    With,
+    LParen,
+    RParen,
+}
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                tokens.push(Token::LParen);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '(' || c == ')' || c.is_whitespace() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "WITH" => Token::With,
+                    _ => Token::Ident(word),
+                });
+            }
+        }
+    }
+
+    tokens
+}
+
+struct Parser<'tokens> {
+    tokens: &'tokens [Token],
+    pos: usize,
+}
+
+impl<'tokens> Parser<'tokens> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // expr := and_expr (OR and_expr)*
+    fn parse_expr(&mut self) -> Result<LicenseExpression, LicenseParseError> {
+        let mut left = self.parse_and_expr()?;
+
+        while matches!(self.peek(), Some(Token::Or)) {
+            self.pos += 1;
+            let right = self.parse_and_expr()?;
+            left = LicenseExpression::Or(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    // and_expr := with_expr (AND with_expr)*
+    fn parse_and_expr(&mut self) -> Result<LicenseExpression, LicenseParseError> {
+        let mut left = self.parse_with_expr()?;
+
+        while matches!(self.peek(), Some(Token::And)) {
+            self.pos += 1;
+            let right = self.parse_with_expr()?;
+            left = LicenseExpression::And(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    // with_expr := atom (WITH IDENT)?
+    fn parse_with_expr(&mut self) -> Result<LicenseExpression, LicenseParseError> {
+        let atom = self.parse_atom()?;
+
+        if matches!(self.peek(), Some(Token::With)) {
+            self.pos += 1;
+            return match self.advance() {
+                Some(Token::Ident(exception)) => {
+                    Ok(LicenseExpression::With(Box::new(atom), exception.clone()))
+                }
+                _ => Err(LicenseParseError::Malformed(
+                    "WITH must be followed by an exception identifier",
+                )),
+            };
+        }
+
+        Ok(atom)
+    }
+
+    // atom := IDENT | '(' expr ')'
+    fn parse_atom(&mut self) -> Result<LicenseExpression, LicenseParseError> {
+        match self.advance() {
+            Some(Token::Ident(id)) if KNOWN_SPDX_IDENTIFIERS.contains(&id.as_str()) => {
+                Ok(LicenseExpression::Id(id.clone()))
+            }
+            Some(Token::Ident(id)) => Err(LicenseParseError::UnknownIdentifier(id.clone())),
+            Some(Token::LParen) => {
+                let inner = self.parse_expr()?;
+                match self.advance() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err(LicenseParseError::Malformed("unbalanced parentheses")),
+                }
+            }
+            _ => Err(LicenseParseError::Malformed(
+                "expected a license identifier or '('",
+            )),
+        }
+    }
+}
+
+pub fn parse(expression: &str) -> Result<LicenseExpression, LicenseParseError> {
+    let tokens = tokenize(expression);
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+
+    let parsed = parser.parse_expr()?;
+
+    if parser.pos != tokens.len() {
+        return Err(LicenseParseError::Malformed(
+            "trailing tokens after a complete license expression",
+        ));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_single_known_identifier() {
+        assert_eq!(parse("MIT"), Ok(LicenseExpression::Id("MIT".to_string())));
+    }
+
+    #[test]
+    fn rejects_an_unknown_identifier() {
+        assert_eq!(
+            parse("Definitely-Not-A-Real-License"),
+            Err(LicenseParseError::UnknownIdentifier("Definitely-Not-A-Real-License".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_and_expression() {
+        assert_eq!(
+            parse("MIT AND Apache-2.0"),
+            Ok(LicenseExpression::And(
+                Box::new(LicenseExpression::Id("MIT".to_string())),
+                Box::new(LicenseExpression::Id("Apache-2.0".to_string())),
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_or_expression() {
+        assert_eq!(
+            parse("MIT OR Apache-2.0"),
+            Ok(LicenseExpression::Or(
+                Box::new(LicenseExpression::Id("MIT".to_string())),
+                Box::new(LicenseExpression::Id("Apache-2.0".to_string())),
+            ))
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // `A OR B AND C` must parse as `A OR (B AND C)`, matching SPDX's documented precedence.
+        assert_eq!(
+            parse("MIT OR ISC AND Zlib"),
+            Ok(LicenseExpression::Or(
+                Box::new(LicenseExpression::Id("MIT".to_string())),
+                Box::new(LicenseExpression::And(
+                    Box::new(LicenseExpression::Id("ISC".to_string())),
+                    Box::new(LicenseExpression::Id("Zlib".to_string())),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            parse("(MIT OR ISC) AND Zlib"),
+            Ok(LicenseExpression::And(
+                Box::new(LicenseExpression::Or(
+                    Box::new(LicenseExpression::Id("MIT".to_string())),
+                    Box::new(LicenseExpression::Id("ISC".to_string())),
+                )),
+                Box::new(LicenseExpression::Id("Zlib".to_string())),
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_with_exception() {
+        assert_eq!(
+            parse("GPL-2.0-only WITH Classpath-exception"),
+            Ok(LicenseExpression::With(
+                Box::new(LicenseExpression::Id("GPL-2.0-only".to_string())),
+                "Classpath-exception".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_unbalanced_parentheses() {
+        assert_eq!(
+            parse("(MIT AND Apache-2.0"),
+            Err(LicenseParseError::Malformed("unbalanced parentheses"))
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_tokens() {
+        assert_eq!(
+            parse("MIT Apache-2.0"),
+            Err(LicenseParseError::Malformed(
+                "trailing tokens after a complete license expression"
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_with_missing_exception_identifier() {
+        assert_eq!(
+            parse("MIT WITH"),
+            Err(LicenseParseError::Malformed(
+                "WITH must be followed by an exception identifier"
+            ))
+        );
+    }
+}
@@ -0,0 +1,6 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+pub mod license;
+mod resolve;
+pub mod system;
@@ -0,0 +1,406 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::process::Command;
+
+use over::obj::Obj;
+
+use super::system::{
+    from_over_obj_at_depth, Executable, Extends, SchemaParsingError, SystemSchema20210801, Using,
+};
+
+// Maximum number of `extends` hops we'll follow before giving up. Chosen generously: real
+// workspace-inheritance chains are rarely more than a handful deep, so this is a backstop
+// against misconfiguration rather than a limit anyone should bump into in practice.
+const MAX_EXTENDS_DEPTH: usize = 16;
+
+// (upstream, rev-or-"HEAD", in-repo path) identifies a single extended document. Used to catch
+// an `extends` chain that loops back on itself.
+pub(crate) type SeenExtends = HashSet<(String, String, String)>;
+
+pub fn resolve(
+    system: SystemSchema20210801,
+) -> Result<SystemSchema20210801, SchemaParsingError> {
+    let mut seen = SeenExtends::new();
+    resolve_with_state(system, &mut seen, 0)
+}
+
+pub(crate) fn resolve_with_state(
+    mut system: SystemSchema20210801,
+    seen: &mut SeenExtends,
+    depth: usize,
+) -> Result<SystemSchema20210801, SchemaParsingError> {
+    let extends = match system.extends.take() {
+        Some(extends) => extends,
+        None => return Ok(system),
+    };
+
+    if !extends.is_empty() && depth >= MAX_EXTENDS_DEPTH {
+        return Err(SchemaParsingError::ExtendsDepthExceeded(MAX_EXTENDS_DEPTH));
+    }
+
+    for extend in &extends {
+        let key = cache_key(&extend.repo, &extend.path);
+
+        if !seen.insert(key.clone()) {
+            return Err(SchemaParsingError::CyclicExtends(format!(
+                "{} (rev {}) @ {}",
+                key.0, key.1, key.2
+            )));
+        }
+
+        let parent = resolve_one(extend, seen, depth + 1)?;
+        seen.remove(&key);
+
+        system = merge(system, parent);
+    }
+
+    Ok(system)
+}
+
+// The `Obj` backing the extended document is loaded and dropped entirely within this function;
+// `from_over_obj_at_depth`'s result therefore has to own all of its data (see system.rs) rather
+// than borrow out of it, or it could never be returned up to `resolve_with_state`.
+fn resolve_one(
+    extend: &Extends,
+    seen: &mut SeenExtends,
+    depth: usize,
+) -> Result<SystemSchema20210801, SchemaParsingError> {
+    let checkout = checkout_using(&extend.repo)?;
+    let doc_path = checkout.join(&extend.path);
+
+    let obj = Obj::from_file(doc_path.to_string_lossy().as_ref())
+        .map_err(|_| SchemaParsingError::ExtendingNonExistantRepo(extend.path.clone()))?;
+
+    let parent_file = from_over_obj_at_depth(&obj, seen, depth)?;
+
+    filter_top_level(parent_file.system, extend.pick.as_ref(), extend.omit.as_ref())
+}
+
+fn cache_key(using: &Using, path: &str) -> (String, String, String) {
+    match using {
+        Using::Git { upstream, rev, .. } => (
+            upstream.clone(),
+            rev.clone().unwrap_or_else(|| "HEAD".to_string()),
+            path.to_string(),
+        ),
+    }
+}
+
+fn checkout_using(using: &Using) -> Result<PathBuf, SchemaParsingError> {
+    match using {
+        Using::Git {
+            upstream,
+            rev,
+            shallow,
+            bin,
+        } => checkout_git(upstream, rev.as_deref(), *shallow, bin),
+    }
+}
+
+fn git_executable(bin: &Executable) -> &str {
+    match bin {
+        Executable::Discoverable(name) => name,
+        Executable::UserProvided(name) => name.as_str(),
+    }
+}
+
+fn checkout_git(
+    upstream: &str,
+    rev: Option<&str>,
+    shallow: bool,
+    bin: &Executable,
+) -> Result<PathBuf, SchemaParsingError> {
+    let git = git_executable(bin);
+    let dest = cache_dir_for(upstream, rev);
+
+    if !dest.exists() {
+        let mut clone = Command::new(git);
+        clone.arg("clone");
+        if shallow {
+            clone.arg("--depth").arg("1");
+        }
+        clone.arg(upstream).arg(&dest);
+        run(&mut clone, upstream)?;
+    }
+
+    if let Some(rev) = rev {
+        let mut checkout = Command::new(git);
+        checkout.arg("-C").arg(&dest).arg("checkout").arg(rev);
+        run(&mut checkout, upstream)?;
+    }
+
+    Ok(dest)
+}
+
+fn run(command: &mut Command, upstream: &str) -> Result<(), SchemaParsingError> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(SchemaParsingError::GitOperationFailed {
+            upstream: upstream.to_string(),
+            problem: format!("exited with {}", status),
+        }),
+        Err(err) => Err(SchemaParsingError::GitOperationFailed {
+            upstream: upstream.to_string(),
+            problem: err.to_string(),
+        }),
+    }
+}
+
+fn cache_dir_for(upstream: &str, rev: Option<&str>) -> PathBuf {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut hasher = DefaultHasher::new();
+    upstream.hash(&mut hasher);
+    rev.hash(&mut hasher);
+
+    let mut dir = std::env::temp_dir();
+    dir.push("oof-extends");
+    dir.push(format!("{:016x}", hasher.finish()));
+    dir
+}
+
+// Applies an `extends[].pick`/`extends[].omit` filter to a resolved parent config, dropping
+// whichever top-level fields weren't asked for (pick) or were explicitly excluded (omit).
+// `target`, `using`, and `extends` are never importable this way: the former two only ever make
+// sense locally, and the latter was already consumed during the parent's own resolution.
+fn filter_top_level(
+    mut parent: SystemSchema20210801,
+    pick: Option<&Vec<String>>,
+    omit: Option<&Vec<String>>,
+) -> Result<SystemSchema20210801, SchemaParsingError> {
+    if let Some(pick) = pick {
+        let keep: HashSet<&str> = pick.iter().map(String::as_str).collect();
+
+        if !keep.contains("disks") {
+            parent.disks = None;
+        }
+        if !keep.contains("linux_kernels") {
+            parent.linux_kernels = None;
+        }
+        if !keep.contains("users") {
+            parent.users = None;
+        }
+        if !keep.contains("groups") {
+            parent.groups = None;
+        }
+        if !keep.contains("shells") {
+            parent.shells = None;
+        }
+        if !keep.contains("privesc") {
+            parent.privesc = None;
+        }
+        if !keep.contains("intentpkgs") {
+            parent.intentpkgs = None;
+        }
+        if !keep.contains("rawpkgs") {
+            parent.rawpkgs = None;
+        }
+    } else if let Some(omit) = omit {
+        for key in omit {
+            match key.as_str() {
+                "disks" => parent.disks = None,
+                "linux_kernels" => parent.linux_kernels = None,
+                "users" => parent.users = None,
+                "groups" => parent.groups = None,
+                "shells" => parent.shells = None,
+                "privesc" => parent.privesc = None,
+                "intentpkgs" => parent.intentpkgs = None,
+                "rawpkgs" => parent.rawpkgs = None,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(parent)
+}
+
+// The extending (local) document always wins on scalars; maps merge key-by-key with the local
+// entry taking precedence on collision; vectors concatenate local-then-parent.
+fn merge(
+    local: SystemSchema20210801,
+    parent: SystemSchema20210801,
+) -> SystemSchema20210801 {
+    SystemSchema20210801 {
+        target: local.target,
+        using: local.using,
+        extends: None,
+        disks: concat_vec(local.disks, parent.disks),
+        linux_kernels: concat_vec(local.linux_kernels, parent.linux_kernels),
+        users: merge_map(local.users, parent.users),
+        groups: merge_map(local.groups, parent.groups),
+        shells: merge_map(local.shells, parent.shells),
+        privesc: local.privesc.or(parent.privesc),
+        intentpkgs: concat_vec(local.intentpkgs, parent.intentpkgs),
+        rawpkgs: concat_vec(local.rawpkgs, parent.rawpkgs),
+    }
+}
+
+fn concat_vec<T>(local: Option<Vec<T>>, parent: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (local, parent) {
+        (Some(mut l), Some(p)) => {
+            l.extend(p);
+            Some(l)
+        }
+        (Some(l), None) => Some(l),
+        (None, Some(p)) => Some(p),
+        (None, None) => None,
+    }
+}
+
+fn merge_map<K: Hash + Eq, V>(
+    local: Option<HashMap<K, V>>,
+    parent: Option<HashMap<K, V>>,
+) -> Option<HashMap<K, V>> {
+    match (local, parent) {
+        (Some(mut l), Some(p)) => {
+            for (k, v) in p {
+                l.entry(k).or_insert(v);
+            }
+            Some(l)
+        }
+        (Some(l), None) => Some(l),
+        (None, Some(p)) => Some(p),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::system::{Target, TargetType};
+
+    fn empty_system() -> SystemSchema20210801 {
+        SystemSchema20210801 {
+            target: Target { target_type: TargetType::TargetSelf },
+            using: HashMap::new(),
+            extends: None,
+            disks: None,
+            linux_kernels: None,
+            users: None,
+            groups: None,
+            shells: None,
+            privesc: None,
+            intentpkgs: None,
+            rawpkgs: None,
+        }
+    }
+
+    #[test]
+    fn merge_concatenates_vecs_local_first() {
+        let mut local = empty_system();
+        local.rawpkgs = Some(vec!["local-pkg".to_string()]);
+
+        let mut parent = empty_system();
+        parent.rawpkgs = Some(vec!["parent-pkg".to_string()]);
+
+        let merged = merge(local, parent);
+
+        assert_eq!(
+            merged.rawpkgs,
+            Some(vec!["local-pkg".to_string(), "parent-pkg".to_string()])
+        );
+    }
+
+    #[test]
+    fn merge_maps_prefer_local_entry_on_key_collision() {
+        let mut local_map: HashMap<&str, u8> = HashMap::new();
+        local_map.insert("bash", 1);
+        let mut parent_map: HashMap<&str, u8> = HashMap::new();
+        parent_map.insert("bash", 2);
+        parent_map.insert("zsh", 3);
+
+        let merged = merge_map(Some(local_map), Some(parent_map)).unwrap();
+
+        assert_eq!(merged.get("bash"), Some(&1));
+        assert_eq!(merged.get("zsh"), Some(&3));
+    }
+
+    #[test]
+    fn merge_scalar_options_prefer_local_over_parent() {
+        use crate::schemas::system::{PrivEscMethod, Privesc};
+
+        let mut local = empty_system();
+        local.privesc = Some(Privesc { method: PrivEscMethod::Doas, config_file: None });
+
+        let mut parent = empty_system();
+        parent.privesc = Some(Privesc { method: PrivEscMethod::Sudo, config_file: None });
+
+        let merged = merge(local, parent);
+
+        assert!(matches!(merged.privesc, Some(Privesc { method: PrivEscMethod::Doas, .. })));
+    }
+
+    #[test]
+    fn concat_vec_handles_either_side_missing() {
+        assert_eq!(concat_vec(Some(vec![1]), None), Some(vec![1]));
+        assert_eq!(concat_vec(None, Some(vec![2])), Some(vec![2]));
+        assert_eq!(concat_vec::<i32>(None, None), None);
+    }
+
+    #[test]
+    fn cache_key_defaults_missing_rev_to_head() {
+        let using = Using::Git {
+            upstream: "https://example.com/repo.git".to_string(),
+            rev: None,
+            shallow: false,
+            bin: Executable::Discoverable("git"),
+        };
+
+        assert_eq!(
+            cache_key(&using, "system.over.oof"),
+            (
+                "https://example.com/repo.git".to_string(),
+                "HEAD".to_string(),
+                "system.over.oof".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn seen_extends_flags_a_repeated_key_as_a_cycle() {
+        let mut seen = SeenExtends::new();
+        let key = cache_key(
+            &Using::Git {
+                upstream: "https://example.com/repo.git".to_string(),
+                rev: None,
+                shallow: false,
+                bin: Executable::Discoverable("git"),
+            },
+            "system.over.oof",
+        );
+
+        assert!(seen.insert(key.clone()), "first visit should not already be seen");
+        assert!(!seen.insert(key), "revisiting the same (upstream, rev, path) must be flagged");
+    }
+
+    #[test]
+    fn filter_top_level_pick_keeps_only_named_fields() {
+        let mut parent = empty_system();
+        parent.rawpkgs = Some(vec!["kept".to_string()]);
+        parent.intentpkgs = Some(vec![]);
+
+        let pick = vec!["rawpkgs".to_string()];
+        let filtered = filter_top_level(parent, Some(&pick), None).unwrap();
+
+        assert_eq!(filtered.rawpkgs, Some(vec!["kept".to_string()]));
+        assert!(filtered.intentpkgs.is_none());
+    }
+
+    #[test]
+    fn filter_top_level_omit_drops_only_named_fields() {
+        let mut parent = empty_system();
+        parent.rawpkgs = Some(vec!["kept".to_string()]);
+        parent.intentpkgs = Some(vec![]);
+
+        let omit = vec!["intentpkgs".to_string()];
+        let filtered = filter_top_level(parent, None, Some(&omit)).unwrap();
+
+        assert_eq!(filtered.rawpkgs, Some(vec!["kept".to_string()]));
+        assert!(filtered.intentpkgs.is_none());
+    }
+}
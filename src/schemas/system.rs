@@ -5,14 +5,40 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 
 use console::style;
+use over::error::OverError;
 use over::obj::Obj;
+use over::value::Value;
+
+use super::license::{self, LicenseExpression, LicenseParseError};
+use super::resolve::{self, SeenExtends};
 
 const COULD_NOT_BE_PARSED_AS_STRING: &'static str = "could not be parsed as a String";
 const COULD_NOT_BE_PARSED_AS_OBJ: &'static str = "could not be parsed as an Object";
 const COULD_NOT_BE_PARSED_AS_ARR: &'static str = "could not be parsed as a homogenous Array";
+const COULD_NOT_BE_PARSED_AS_INT: &'static str = "could not be parsed as an Integer";
 const COULD_NOT_DETERMINE_REPO_TYPE: &'static str = "could not determine repo type (eg. git)";
 const MAINTAINER_OR_HOMEPAGE_REQUIRED: &'static str =
     "meta.maintainer and/or meta.homepage is required";
+const PICK_AND_OMIT_ARE_MUTUALLY_EXCLUSIVE: &'static str =
+    "pick and omit are mutually exclusive";
+const COULD_NOT_BE_PARSED_AS_PORT: &'static str = "could not be parsed as a port number";
+const UNKNOWN_TARGET_TYPE: &'static str = "must specify exactly one of: self, ssh, chroot, image";
+const UNKNOWN_PRIVESC_METHOD: &'static str = "must be one of: doas, sudo";
+const UNKNOWN_DISK_TYPE: &'static str =
+    "must be one of: bcachefs, btrfs, ext2, ext3, ext4, jfs, nilfs2, ntfs, swap, tmpfs, vfat, xfs, zfs";
+const UNKNOWN_FSCK_ORDER: &'static str = "must be one of: disabled, first, next";
+const COULD_NOT_BE_PARSED_AS_VERSION_REQ: &'static str =
+    "could not be parsed as a semver version requirement";
+const UNKNOWN_MATCH_BY: &'static str = "must be one of: name, id";
+const UNKNOWN_IGNORABLE_ERROR_BEHAVIOR: &'static str = "must be one of: error, warn, ignore";
+const UNKNOWN_ERROR_BEHAVIOR: &'static str = "must be one of: error, warn";
+const UNKNOWN_SECURABLE_INPUT_TYPE: &'static str =
+    "must specify exactly one of: raw, file, file_gpg, prompt_once, prompt_always";
+const COULD_NOT_BE_PARSED_AS_INTENTPKG_OPT: &'static str =
+    "could not be parsed as a Null, Boolean, Number, String, Array, or Object";
+const INTENTPKG_OPT_LIST_MUST_HAVE_EXACTLY_ONE_ELEMENT: &'static str =
+    "IntentPkgOpt::List holds a single value, not a sequence: arrays of more than one element \
+     aren't representable here";
 
 // TODO everything in this chunk needs to find a reusable home
 
@@ -28,8 +54,15 @@ pub enum SchemaParsingError {
     UnsupportedSchemaVersion {
         schema_type: String,
         requested_version: String,
+        supported_versions: Vec<String>,
     },
     ExtendingNonExistantRepo(String),
+    CyclicExtends(String),
+    ExtendsDepthExceeded(usize),
+    GitOperationFailed { upstream: String, problem: String },
+    BuildFailed { package: String, problem: String },
+    UnknownLicenseIdentifier(String),
+    SecretResolutionFailed { field: String, problem: String },
 }
 
 #[derive(Debug)]
@@ -54,11 +87,13 @@ pub enum SecurableInput {
         key: String,
         executable: Option<String>,
     },
-    PromptOnce,
+    // `group` names the prompt: two `PromptOnce` fields that share a group are asking for the
+    // same secret and should only prompt once between them (see `secrets::SecretResolver`).
+    PromptOnce { group: String },
     PromptAlways,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Executable {
     Discoverable(&'static str),
     UserProvided(String),
@@ -67,19 +102,25 @@ pub enum Executable {
 #[derive(Debug)]
 pub struct OofFile {
     schema: OofFileSchema,
-    meta: OofFileMeta,
+    pub(crate) meta: OofFileMeta,
+    pub system: SystemSchema20210801,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum OofFileSchema {
     System20210801,
 }
 
+// Versions supported for the `system` schema type, newest-last isn't required: selection always
+// picks the newest match regardless of registration order.
+const SYSTEM_SCHEMA_VERSIONS: &[(&str, OofFileSchema)] = &[("2021.08.01", OofFileSchema::System20210801)];
+
 #[derive(Debug)]
 pub struct OofFileMeta {
     maintainer: Option<OofFileMetaMaintainer>,
     homepage: Option<String>,
     license: OofFileLicense,
+    pub(crate) build: Option<BuildConfig>,
 }
 
 #[derive(Debug)]
@@ -88,42 +129,65 @@ pub struct OofFileMetaMaintainer {
     contact: Option<String>,
 }
 
+// Configures the container-based source-build backend (see `crate::build`): the base image
+// builds run against, and where on the host their resulting packages land.
+#[derive(Debug)]
+pub struct BuildConfig {
+    pub(crate) image: String,
+    pub(crate) output_dir: PathBuf,
+}
+
 #[derive(Debug)]
 pub enum OofFileLicense {
     Restricted,
-    SPDXIdentifier(String),
+    Expression(LicenseExpression),
 }
 
 // below here should all be schema-specific
 
+// Every field here is `pub(crate)`: the `resolve` submodule reads and rewrites all of them while
+// merging an `extends` chain, and owns no special access of its own beyond crate visibility.
 #[derive(Debug)]
-pub struct SystemSchema20210801<'config> {
-    target: Target,
-    using: UsingMap,
-    extends: Option<Vec<Extends<'config>>>,
-    disks: Option<Vec<Disk>>,
-    linux_kernels: Option<Vec<LinuxKernel>>,
-    users: Option<HashMap<&'config str, User<'config>>>,
-    groups: Option<HashMap<&'config str, Group>>,
-    shells: Option<HashMap<String, Shell>>,
-    privesc: Option<Privesc>,
-    intentpkgs: Option<Vec<IntentPkg>>,
-    rawpkgs: Option<Vec<String>>,
+pub struct SystemSchema20210801 {
+    pub(crate) target: Target,
+    pub(crate) using: UsingMap,
+    pub(crate) extends: Option<Vec<Extends>>,
+    pub(crate) disks: Option<Vec<Disk>>,
+    pub(crate) linux_kernels: Option<Vec<LinuxKernel>>,
+    pub(crate) users: Option<HashMap<String, User>>,
+    pub(crate) groups: Option<HashMap<String, Group>>,
+    pub(crate) shells: Option<HashMap<String, Shell>>,
+    pub(crate) privesc: Option<Privesc>,
+    pub(crate) intentpkgs: Option<Vec<IntentPkg>>,
+    pub(crate) rawpkgs: Option<Vec<String>>,
 }
 
 #[derive(Debug)]
 pub struct Target {
-    target_type: TargetType,
+    pub(crate) target_type: TargetType,
 }
 
 #[derive(Debug)]
 pub enum TargetType {
     TargetSelf,
+    Ssh {
+        host: String,
+        port: u16,
+        user: String,
+        identity_file: Option<PathBuf>,
+        privesc: Option<Privesc>,
+    },
+    Chroot {
+        mountpoint: PathBuf,
+    },
+    Image {
+        path: PathBuf,
+    },
 }
 
 type UsingMap = HashMap<String, Using>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Using {
     Git {
         upstream: String,
@@ -133,19 +197,22 @@ pub enum Using {
     },
 }
 
-#[derive(Debug)]
-pub struct Extends<'outer_config> {
-    repo: &'outer_config Using,
-    path: String,
-    pick: Option<Vec<String>>,
-    omit: Option<Vec<String>>,
+// `repo` is a clone of the referenced `using.*` entry rather than a borrow of it: an `Extends`
+// outlives the local `using` map once resolution moves it into a merged `SystemSchema20210801`,
+// so it needs to own its copy.
+#[derive(Debug, Clone)]
+pub struct Extends {
+    pub(crate) repo: Using,
+    pub(crate) path: String,
+    pub(crate) pick: Option<Vec<String>>,
+    pub(crate) omit: Option<Vec<String>>,
 }
 
 #[derive(Debug)]
 pub struct Disk {
-    source: String,
-    mountpoint: String,
-    disk_type: DiskType,
+    pub(crate) source: String,
+    pub(crate) mountpoint: String,
+    pub(crate) disk_type: DiskType,
     options: Vec<String>,
     dump: bool,
     fsck_order: FsckOrder,
@@ -193,19 +260,19 @@ pub enum LinuxKernelSeries {
 }
 
 #[derive(Debug)]
-pub struct User<'outer_config> {
-    name: String,
-    is_system: bool,
-    uid: Option<u32>,
-    main_group: String,
+pub struct User {
+    pub(crate) name: String,
+    pub(crate) is_system: bool,
+    pub(crate) uid: Option<u32>,
+    pub(crate) main_group: String,
     extra_groups: Option<Vec<String>>,
     full_name: Option<String>,
-    shell: &'outer_config UserShellRef,
+    shell: UserShellRef,
     install_missing_shell: bool,
-    password: Option<SecurableInput>,
+    pub(crate) password: Option<SecurableInput>,
     state_stub: bool,
     match_by: UserOrGroupMatchBy,
-    not_matched_error_behavior: IgnorableErrorBehavior,
+    pub(crate) not_matched_error_behavior: IgnorableErrorBehavior,
     prune_on_removal: bool,
 }
 
@@ -223,9 +290,9 @@ pub enum UserOrGroupMatchBy {
 
 #[derive(Debug)]
 pub struct Group {
-    name: String,
-    is_system: bool,
-    gid: Option<u32>,
+    pub(crate) name: String,
+    pub(crate) is_system: bool,
+    pub(crate) gid: Option<u32>,
     state_stub: bool,
     match_by: UserOrGroupMatchBy,
     not_matched_error_behavior: IgnorableErrorBehavior,
@@ -239,13 +306,13 @@ pub struct Shell {
     system_config_file: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Privesc {
-    method: PrivEscMethod,
-    config_file: Option<PathBuf>,
+    pub(crate) method: PrivEscMethod,
+    pub(crate) config_file: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PrivEscMethod {
     Doas,
     Sudo,
@@ -253,8 +320,8 @@ pub enum PrivEscMethod {
 
 #[derive(Debug)]
 pub struct IntentPkg {
-    name: String,
-    opts: Option<HashMap<String, IntentPkgOpt>>,
+    pub(crate) name: String,
+    pub(crate) opts: Option<HashMap<String, IntentPkgOpt>>,
 }
 
 #[derive(Debug)]
@@ -268,6 +335,20 @@ pub enum IntentPkgOpt {
 }
 
 pub fn from_over_obj(obj: &Obj) -> Result<OofFile, SchemaParsingError> {
+    let mut seen = SeenExtends::new();
+    from_over_obj_at_depth(obj, &mut seen, 0)
+}
+
+// Entry point used both by the public `from_over_obj` (depth 0, an empty cycle-detection set)
+// and recursively by the `extends` resolver, which threads its own in-progress state through so
+// a chain of documents extending one another can't loop forever. The parsed result owns all of
+// its data rather than borrowing out of `obj`, since the resolver loads (and drops) one `Obj` per
+// extended document and needs to hand the parsed config back up past that document's lifetime.
+pub(crate) fn from_over_obj_at_depth(
+    obj: &Obj,
+    seen: &mut SeenExtends,
+    depth: usize,
+) -> Result<OofFile, SchemaParsingError> {
     let oof_instruction_obj = match obj.get_obj(&"oof") {
         Ok(oof) => oof,
         Err(_) => {
@@ -289,13 +370,9 @@ pub fn from_over_obj(obj: &Obj) -> Result<OofFile, SchemaParsingError> {
         oof_meta
     );
 
-    // TODO eventually, actually parse what the user asked for here. For now, since there is
-    // exactly one supported value, let's just hard-code and move on.
-    let target = Target {
-        target_type: TargetType::TargetSelf,
-    };
+    let target = parse_target(&obj)?;
     eprintln!(
-        "{} hard-coded target block (lol): {:?}",
+        "{} parsed target: {:?}",
         style("successfully").green(),
         target
     );
@@ -314,28 +391,50 @@ pub fn from_over_obj(obj: &Obj) -> Result<OofFile, SchemaParsingError> {
         extends
     );
 
-    Err(SchemaParsingError::Generic("rest not implemented"))
+    let system = SystemSchema20210801 {
+        target,
+        using,
+        extends: if extends.is_empty() { None } else { Some(extends) },
+        disks: parse_disks(&obj)?,
+        linux_kernels: parse_linux_kernels(&obj)?,
+        users: parse_users(&obj)?,
+        groups: parse_groups(&obj)?,
+        shells: parse_shells(&obj)?,
+        privesc: parse_top_level_privesc(&obj)?,
+        intentpkgs: parse_intentpkgs(&obj)?,
+        rawpkgs: parse_rawpkgs(&obj)?,
+    };
+
+    let system = resolve::resolve_with_state(system, seen, depth)?;
+    eprintln!(
+        "{} resolved extends chain",
+        style("successfully").green(),
+    );
+
+    Ok(OofFile {
+        schema: oof_schema,
+        meta: oof_meta,
+        system,
+    })
 }
 
 fn parse_oof_schema_type(oof: &Obj) -> Result<OofFileSchema, SchemaParsingError> {
     match oof.get_obj(&"schema") {
         Ok(schema) => match schema.get_str(&"type") {
-            Ok(schema_type) => match schema_type.as_str() {
-                "system" => match schema.get_str(&"version") {
-                    Ok(version) => match version.as_str() {
-                        "2021.08.01" => Ok(OofFileSchema::System20210801),
-                        _ => Err(SchemaParsingError::UnsupportedSchemaVersion {
-                            schema_type: schema_type,
-                            requested_version: version,
-                        }),
-                    },
+            Ok(schema_type) => {
+                let registry = match registry_for(&schema_type) {
+                    Some(registry) => registry,
+                    None => return Err(SchemaParsingError::UnsupportedSchemaType(schema_type)),
+                };
+
+                match schema.get_str(&"version") {
+                    Ok(version) => select_schema_version(&schema_type, &version, registry),
                     Err(_) => Err(SchemaParsingError::MalformedOofInstruction {
                         field_name: "oof.schema.version".to_string(),
                         problem: COULD_NOT_BE_PARSED_AS_STRING,
                     }),
-                },
-                _ => Err(SchemaParsingError::UnsupportedSchemaType(schema_type)),
-            },
+                }
+            }
             Err(_) => Err(SchemaParsingError::MalformedOofInstruction {
                 field_name: "oof.schema.type".to_string(),
                 problem: COULD_NOT_BE_PARSED_AS_STRING,
@@ -348,6 +447,102 @@ fn parse_oof_schema_type(oof: &Obj) -> Result<OofFileSchema, SchemaParsingError>
     }
 }
 
+fn registry_for(schema_type: &str) -> Option<&'static [(&'static str, OofFileSchema)]> {
+    match schema_type {
+        "system" => Some(SYSTEM_SCHEMA_VERSIONS),
+        _ => None,
+    }
+}
+
+// Accepts either an exact version string matching a registered entry, or a `semver::VersionReq`
+// (e.g. `">=2021.08.01, <2022"`), in which case the newest registered version satisfying it is
+// selected. This lets a
+// document pin an exact schema release or opt into forward compatibility.
+fn select_schema_version(
+    schema_type: &str,
+    requested: &str,
+    registry: &[(&'static str, OofFileSchema)],
+) -> Result<OofFileSchema, SchemaParsingError> {
+    if let Some((_, schema)) = registry.iter().find(|(version, _)| *version == requested) {
+        return Ok(*schema);
+    }
+
+    if let Ok(req) = semver::VersionReq::parse(&normalize_version_req(requested)) {
+        let mut candidates: Vec<(semver::Version, OofFileSchema)> = registry
+            .iter()
+            .filter_map(|(version, schema)| version_to_semver(version).map(|parsed| (parsed, *schema)))
+            .filter(|(parsed, _)| req.matches(parsed))
+            .collect();
+
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if let Some((_, schema)) = candidates.pop() {
+            return Ok(schema);
+        }
+    }
+
+    Err(SchemaParsingError::UnsupportedSchemaVersion {
+        schema_type: schema_type.to_string(),
+        requested_version: requested.to_string(),
+        supported_versions: registry.iter().map(|(version, _)| version.to_string()).collect(),
+    })
+}
+
+// Our version strings are dotted dates (`2021.08.01`), not semver proper: parse them
+// component-by-component rather than feeding them straight to `semver::Version::parse`, which
+// would reject the leading zeros.
+fn version_to_semver(version: &str) -> Option<semver::Version> {
+    let mut parts = version.split('.');
+    let major = parse_version_component(parts.next()?)?;
+    let minor = parse_version_component(parts.next()?)?;
+    let patch = parse_version_component(parts.next()?)?;
+
+    Some(semver::Version::new(major, minor, patch))
+}
+
+fn parse_version_component(raw: &str) -> Option<u64> {
+    if raw.is_empty() {
+        return None;
+    }
+
+    match raw.trim_start_matches('0') {
+        "" => Some(0),
+        trimmed => trimmed.parse().ok(),
+    }
+}
+
+// `VersionReq::parse` runs the same strict-semver digit grammar as `Version::parse`, so a range
+// like `">=2021.08.01, <2022"` is rejected over the leading zero in `08` before we ever get a
+// chance to compare it against a candidate. Strip leading zeros from every digit run in the
+// requirement string up front so the comparators parse the same dotted-date numbers
+// `version_to_semver` produces.
+fn normalize_version_req(requested: &str) -> String {
+    let mut normalized = String::with_capacity(requested.len());
+    let mut digits = String::new();
+
+    let flush = |digits: &mut String, normalized: &mut String| {
+        if !digits.is_empty() {
+            match digits.trim_start_matches('0') {
+                "" => normalized.push('0'),
+                trimmed => normalized.push_str(trimmed),
+            }
+            digits.clear();
+        }
+    };
+
+    for c in requested.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            flush(&mut digits, &mut normalized);
+            normalized.push(c);
+        }
+    }
+    flush(&mut digits, &mut normalized);
+
+    normalized
+}
+
 fn parse_oof_meta(oof: &Obj) -> Result<OofFileMeta, SchemaParsingError> {
     match oof.get_obj(&"meta") {
         Ok(meta) => {
@@ -364,13 +559,27 @@ fn parse_oof_meta(oof: &Obj) -> Result<OofFileMeta, SchemaParsingError> {
             if let Ok(license) = meta.get_str(&"license") {
                 let lowercased_license = license.to_lowercase();
 
+                let license = match lowercased_license.as_str() {
+                    "restricted" | "proprietary" => OofFileLicense::Restricted,
+                    _ => match license::parse(&license) {
+                        Ok(expression) => OofFileLicense::Expression(expression),
+                        Err(LicenseParseError::UnknownIdentifier(id)) => {
+                            return Err(SchemaParsingError::UnknownLicenseIdentifier(id));
+                        }
+                        Err(LicenseParseError::Malformed(problem)) => {
+                            return Err(SchemaParsingError::MalformedOofInstruction {
+                                field_name: "oof.meta.license".to_string(),
+                                problem,
+                            });
+                        }
+                    },
+                };
+
                 return Ok(OofFileMeta {
                     maintainer: maintainer.ok(),
                     homepage: homepage.ok(),
-                    license: match lowercased_license.as_str() {
-                        "restricted" | "proprietary" => OofFileLicense::Restricted,
-                        _ => OofFileLicense::SPDXIdentifier(license),
-                    },
+                    license,
+                    build: parse_oof_meta_build(&meta)?,
                 });
             } else {
                 return Err(SchemaParsingError::MalformedOofInstruction {
@@ -386,6 +595,32 @@ fn parse_oof_meta(oof: &Obj) -> Result<OofFileMeta, SchemaParsingError> {
     }
 }
 
+// `meta.build` is entirely optional: files that only declare `intentpkgs`/`rawpkgs` for use by
+// an extending document don't need a base image of their own.
+fn parse_oof_meta_build(meta: &Obj) -> Result<Option<BuildConfig>, SchemaParsingError> {
+    let build = match meta.get_obj(&"build") {
+        Ok(build) => build,
+        Err(_) => return Ok(None),
+    };
+
+    let image = match build.get_str(&"image") {
+        Ok(image) => image,
+        Err(_) => {
+            return Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: "meta.build.image".to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_STRING,
+            });
+        }
+    };
+
+    let output_dir = build
+        .get_str(&"output_dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("./out"));
+
+    Ok(Some(BuildConfig { image, output_dir }))
+}
+
 fn parse_oof_meta_maintainer(meta: &Obj) -> Result<OofFileMetaMaintainer, SchemaParsingError> {
     match meta.get_obj(&"maintainer") {
         Ok(maintainer) => match maintainer.get_str(&"name") {
@@ -408,6 +643,118 @@ fn parse_oof_meta_maintainer(meta: &Obj) -> Result<OofFileMetaMaintainer, Schema
     }
 }
 
+// Exactly one of `target.self`, `target.ssh`, `target.chroot`, `target.image` is expected; an
+// absent `target` block defaults to `self`, matching oof's original self-configuration-only
+// behavior.
+fn parse_target(config: &Obj) -> Result<Target, SchemaParsingError> {
+    let target_obj = match config.get_obj(&"target") {
+        Ok(target_obj) => target_obj,
+        Err(_) => {
+            return Ok(Target {
+                target_type: TargetType::TargetSelf,
+            });
+        }
+    };
+
+    let target_type = if target_obj.get_obj(&"self").is_ok() {
+        TargetType::TargetSelf
+    } else if let Ok(ssh) = target_obj.get_obj(&"ssh") {
+        parse_target_ssh(&ssh)?
+    } else if let Ok(chroot) = target_obj.get_obj(&"chroot") {
+        parse_target_chroot(&chroot)?
+    } else if let Ok(image) = target_obj.get_obj(&"image") {
+        parse_target_image(&image)?
+    } else {
+        return Err(SchemaParsingError::MalformedOofInstruction {
+            field_name: "target".to_string(),
+            problem: UNKNOWN_TARGET_TYPE,
+        });
+    };
+
+    Ok(Target { target_type })
+}
+
+fn parse_target_ssh(ssh: &Obj) -> Result<TargetType, SchemaParsingError> {
+    let host = ssh.get_str(&"host").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+        field_name: "target.ssh.host".to_string(),
+        problem: COULD_NOT_BE_PARSED_AS_STRING,
+    })?;
+
+    let port = match ssh.get_str(&"port") {
+        Ok(port) => port.parse::<u16>().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: "target.ssh.port".to_string(),
+            problem: COULD_NOT_BE_PARSED_AS_PORT,
+        })?,
+        Err(_) => 22,
+    };
+
+    let user = ssh.get_str(&"user").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+        field_name: "target.ssh.user".to_string(),
+        problem: COULD_NOT_BE_PARSED_AS_STRING,
+    })?;
+
+    let identity_file = ssh.get_str(&"identity_file").ok().map(PathBuf::from);
+
+    let privesc = match ssh.get_obj(&"privesc") {
+        Ok(privesc) => Some(parse_privesc(&privesc)?),
+        Err(_) => None,
+    };
+
+    Ok(TargetType::Ssh {
+        host,
+        port,
+        user,
+        identity_file,
+        privesc,
+    })
+}
+
+fn parse_target_chroot(chroot: &Obj) -> Result<TargetType, SchemaParsingError> {
+    let mountpoint = chroot.get_str(&"mountpoint").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+        field_name: "target.chroot.mountpoint".to_string(),
+        problem: COULD_NOT_BE_PARSED_AS_STRING,
+    })?;
+
+    Ok(TargetType::Chroot {
+        mountpoint: PathBuf::from(mountpoint),
+    })
+}
+
+fn parse_target_image(image: &Obj) -> Result<TargetType, SchemaParsingError> {
+    let path = image.get_str(&"path").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+        field_name: "target.image.path".to_string(),
+        problem: COULD_NOT_BE_PARSED_AS_STRING,
+    })?;
+
+    Ok(TargetType::Image { path: PathBuf::from(path) })
+}
+
+fn parse_privesc(privesc: &Obj) -> Result<Privesc, SchemaParsingError> {
+    let method = match privesc.get_str(&"method") {
+        Ok(method) => match method.to_lowercase().as_str() {
+            "doas" => PrivEscMethod::Doas,
+            "sudo" => PrivEscMethod::Sudo,
+            _ => {
+                return Err(SchemaParsingError::MalformedOofInstruction {
+                    field_name: "privesc.method".to_string(),
+                    problem: UNKNOWN_PRIVESC_METHOD,
+                });
+            }
+        },
+        Err(_) => {
+            return Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: "privesc.method".to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_STRING,
+            });
+        }
+    };
+
+    Ok(Privesc {
+        method,
+        config_file: privesc.get_str(&"config_file").ok().map(PathBuf::from),
+    })
+}
+
 fn parse_using(config: &Obj) -> Result<UsingMap, SchemaParsingError> {
     match config.get_obj(&"using") {
         Ok(using) => {
@@ -447,10 +794,38 @@ fn parse_using(config: &Obj) -> Result<UsingMap, SchemaParsingError> {
     }
 }
 
-fn parse_extends<'using>(
-    config: &Obj,
-    using: &'using UsingMap,
-) -> Result<Vec<Extends<'using>>, SchemaParsingError> {
+// Shared by `pick`/`omit`: a missing field means "no filter", but a present-and-malformed one
+// (not an array, or an array with a non-string entry) must propagate as a real parse error
+// instead of collapsing to the same "no filter" result.
+fn parse_string_array_field(
+    obj: &Obj,
+    field: &str,
+    field_name: &str,
+) -> Result<Option<Vec<String>>, SchemaParsingError> {
+    let arr = match obj.get_arr(&field) {
+        Ok(arr) => arr,
+        Err(OverError::FieldNotFound(_)) => return Ok(None),
+        Err(_) => {
+            return Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_ARR,
+            });
+        }
+    };
+
+    arr.vec_ref()
+        .iter()
+        .map(|value| {
+            value.get_str().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_STRING,
+            })
+        })
+        .collect::<Result<Vec<String>, SchemaParsingError>>()
+        .map(Some)
+}
+
+fn parse_extends(config: &Obj, using: &UsingMap) -> Result<Vec<Extends>, SchemaParsingError> {
     match config.get_arr(&"extends") {
         Ok(extends) => {
             let mut result = Vec::with_capacity(extends.len());
@@ -490,19 +865,21 @@ fn parse_extends<'using>(
                     }
                 };
 
+                let pick = parse_string_array_field(&eobj, "pick", &format!("extends[{}].pick", idx))?;
+                let omit = parse_string_array_field(&eobj, "omit", &format!("extends[{}].omit", idx))?;
+
+                if pick.is_some() && omit.is_some() {
+                    return Err(SchemaParsingError::MalformedOofInstruction {
+                        field_name: format!("extends[{}]", idx),
+                        problem: PICK_AND_OMIT_ARE_MUTUALLY_EXCLUSIVE,
+                    });
+                }
+
                 result.push(Extends {
-                    repo: using.get(&repo).unwrap(),
+                    repo: using.get(&repo).unwrap().clone(),
                     path: path.clone(),
-                    pick: eobj
-                        .get_arr(&"pick")
-                        .and_then(|picks| Ok(picks.vec_ref().to_vec()))
-                        .and_then(|objs| objs.iter().map(|obj| obj.get_str()).collect())
-                        .ok(),
-                    omit: eobj
-                        .get_arr(&"omit")
-                        .and_then(|omits| Ok(omits.vec_ref().to_vec()))
-                        .and_then(|objs| objs.iter().map(|obj| obj.get_str()).collect())
-                        .ok(),
+                    pick,
+                    omit,
                 });
             }
 
@@ -514,3 +891,586 @@ fn parse_extends<'using>(
         }),
     }
 }
+
+// `disks` is entirely optional: a document that only extends a disk layout from elsewhere, or
+// doesn't manage disks at all, simply omits the field.
+fn parse_disks(config: &Obj) -> Result<Option<Vec<Disk>>, SchemaParsingError> {
+    let disks = match config.get_arr(&"disks") {
+        Ok(disks) => disks,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = Vec::with_capacity(disks.len());
+
+    for (idx, draw) in disks.iter().enumerate() {
+        let dobj = draw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}]", idx),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let source = dobj.get_str(&"source").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}].source", idx),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+
+        let mountpoint = dobj.get_str(&"mountpoint").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}].mountpoint", idx),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+
+        let disk_type_raw = dobj.get_str(&"disk_type").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}].disk_type", idx),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+        let disk_type = parse_disk_type(&disk_type_raw, idx)?;
+
+        let options = match dobj.get_arr(&"options") {
+            Ok(options) => options
+                .vec_ref()
+                .iter()
+                .map(|item| item.get_str())
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                    field_name: format!("disks[{}].options", idx),
+                    problem: COULD_NOT_BE_PARSED_AS_ARR,
+                })?,
+            Err(_) => Vec::new(),
+        };
+
+        let dump = dobj.get_bool(&"dump").unwrap_or(false);
+        let fsck_order = match dobj.get_str(&"fsck_order") {
+            Ok(raw) => parse_fsck_order(&raw, idx)?,
+            Err(_) => FsckOrder::Disabled,
+        };
+        let install_userspace_utils = dobj.get_bool(&"install_userspace_utils").unwrap_or(true);
+        let install_kernel_modules = dobj.get_bool(&"install_kernel_modules").unwrap_or(true);
+
+        result.push(Disk {
+            source,
+            mountpoint,
+            disk_type,
+            options,
+            dump,
+            fsck_order,
+            install_userspace_utils,
+            install_kernel_modules,
+        });
+    }
+
+    Ok(Some(result))
+}
+
+fn parse_disk_type(raw: &str, idx: usize) -> Result<DiskType, SchemaParsingError> {
+    match raw.to_lowercase().as_str() {
+        "bcachefs" => Ok(DiskType::Bcachefs),
+        "btrfs" => Ok(DiskType::Btrfs),
+        "ext2" => Ok(DiskType::Ext2),
+        "ext3" => Ok(DiskType::Ext3),
+        "ext4" => Ok(DiskType::Ext4),
+        "jfs" => Ok(DiskType::Jfs),
+        "nilfs2" => Ok(DiskType::Nilfs2),
+        "ntfs" => Ok(DiskType::Ntfs),
+        "swap" => Ok(DiskType::Swap),
+        "tmpfs" => Ok(DiskType::Tmpfs),
+        "vfat" => Ok(DiskType::Vfat),
+        "xfs" => Ok(DiskType::Xfs),
+        "zfs" => Ok(DiskType::Zfs),
+        _ => Err(SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}].disk_type", idx),
+            problem: UNKNOWN_DISK_TYPE,
+        }),
+    }
+}
+
+fn parse_fsck_order(raw: &str, idx: usize) -> Result<FsckOrder, SchemaParsingError> {
+    match raw.to_lowercase().as_str() {
+        "disabled" => Ok(FsckOrder::Disabled),
+        "first" => Ok(FsckOrder::First),
+        "next" => Ok(FsckOrder::Next),
+        _ => Err(SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("disks[{}].fsck_order", idx),
+            problem: UNKNOWN_FSCK_ORDER,
+        }),
+    }
+}
+
+// `linux_kernels` is entirely optional, same reasoning as `disks`.
+fn parse_linux_kernels(config: &Obj) -> Result<Option<Vec<LinuxKernel>>, SchemaParsingError> {
+    let kernels = match config.get_arr(&"linux_kernels") {
+        Ok(kernels) => kernels,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = Vec::with_capacity(kernels.len());
+
+    for (idx, kraw) in kernels.iter().enumerate() {
+        let kobj = kraw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("linux_kernels[{}]", idx),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let series = match kobj.get_str(&"series") {
+            Ok(series) if series.eq_ignore_ascii_case("default") => LinuxKernelSeries::Default,
+            Ok(series) => LinuxKernelSeries::Other(series),
+            Err(OverError::FieldNotFound(_)) => LinuxKernelSeries::Default,
+            Err(_) => {
+                return Err(SchemaParsingError::MalformedOofInstruction {
+                    field_name: format!("linux_kernels[{}].series", idx),
+                    problem: COULD_NOT_BE_PARSED_AS_STRING,
+                });
+            }
+        };
+
+        let versions_raw = kobj.get_str(&"versions").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("linux_kernels[{}].versions", idx),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+        let versions =
+            semver::VersionReq::parse(&versions_raw).map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                field_name: format!("linux_kernels[{}].versions", idx),
+                problem: COULD_NOT_BE_PARSED_AS_VERSION_REQ,
+            })?;
+
+        let install_headers = kobj.get_bool(&"install_headers").unwrap_or(true);
+        let install_firmware = kobj.get_bool(&"install_firmware").ok();
+
+        result.push(LinuxKernel {
+            series,
+            versions,
+            install_headers,
+            install_firmware,
+        });
+    }
+
+    Ok(Some(result))
+}
+
+// Shared by every optional uid/gid-style field: absent means "let the target backend pick one",
+// present-but-unparseable is a hard error rather than silently falling back to that same default.
+fn parse_u32_field(obj: &Obj, field: &str, field_name: &str) -> Result<Option<u32>, SchemaParsingError> {
+    match obj.get_int(field) {
+        Ok(value) => value
+            .to_string()
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_INT,
+            }),
+        Err(OverError::FieldNotFound(_)) => Ok(None),
+        Err(_) => Err(SchemaParsingError::MalformedOofInstruction {
+            field_name: field_name.to_string(),
+            problem: COULD_NOT_BE_PARSED_AS_INT,
+        }),
+    }
+}
+
+fn parse_match_by(obj: &Obj, field_name: &str) -> Result<UserOrGroupMatchBy, SchemaParsingError> {
+    match obj.get_str(&"match_by") {
+        Ok(raw) => match raw.to_lowercase().as_str() {
+            "name" => Ok(UserOrGroupMatchBy::Name),
+            "id" => Ok(UserOrGroupMatchBy::ID),
+            _ => Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: UNKNOWN_MATCH_BY,
+            }),
+        },
+        Err(_) => Ok(UserOrGroupMatchBy::Name),
+    }
+}
+
+fn parse_ignorable_error_behavior(
+    obj: &Obj,
+    field: &str,
+    field_name: &str,
+) -> Result<IgnorableErrorBehavior, SchemaParsingError> {
+    match obj.get_str(field) {
+        Ok(raw) => match raw.to_lowercase().as_str() {
+            "error" => Ok(IgnorableErrorBehavior::Error),
+            "warn" => Ok(IgnorableErrorBehavior::Warn),
+            "ignore" => Ok(IgnorableErrorBehavior::Ignore),
+            _ => Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: UNKNOWN_IGNORABLE_ERROR_BEHAVIOR,
+            }),
+        },
+        Err(_) => Ok(IgnorableErrorBehavior::Warn),
+    }
+}
+
+fn parse_user_shell_ref(raw: &str) -> UserShellRef {
+    if raw.starts_with('/') {
+        UserShellRef::AbsolutePath(PathBuf::from(raw))
+    } else {
+        UserShellRef::BinName(raw.to_string())
+    }
+}
+
+// Exactly one of `raw`, `file`, `file_gpg`, `prompt_once`, `prompt_always` is expected, mirroring
+// how `target` picks its variant (see `parse_target`).
+fn parse_securable_input(secure: &Obj, field_name: &str) -> Result<SecurableInput, SchemaParsingError> {
+    if let Ok(raw) = secure.get_str(&"raw") {
+        return Ok(SecurableInput::Raw(raw));
+    }
+
+    if let Ok(path) = secure.get_str(&"file") {
+        return Ok(SecurableInput::FilePlaintext(path));
+    }
+
+    if let Ok(file_gpg) = secure.get_obj(&"file_gpg") {
+        let path = file_gpg.get_str(&"path").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("{}.file_gpg.path", field_name),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+        let key = file_gpg.get_str(&"key").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("{}.file_gpg.key", field_name),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+        let executable = file_gpg.get_str(&"executable").ok();
+
+        return Ok(SecurableInput::FileGpgNear { path, key, executable });
+    }
+
+    if let Ok(group) = secure.get_str(&"prompt_once") {
+        return Ok(SecurableInput::PromptOnce { group });
+    }
+
+    if secure.get_obj(&"prompt_always").is_ok() {
+        return Ok(SecurableInput::PromptAlways);
+    }
+
+    Err(SchemaParsingError::MalformedOofInstruction {
+        field_name: field_name.to_string(),
+        problem: UNKNOWN_SECURABLE_INPUT_TYPE,
+    })
+}
+
+// `users` is keyed the same way `using` is (see `parse_using`): the key is the logical name used
+// elsewhere in the document, and doubles as the default for `name` unless overridden.
+fn parse_users(config: &Obj) -> Result<Option<HashMap<String, User>>, SchemaParsingError> {
+    let users = match config.get_obj(&"users") {
+        Ok(users) => users,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = HashMap::with_capacity(users.len());
+
+    for (key, uraw) in users.iter() {
+        let uobj = uraw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("users.{}", key),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let name = uobj.get_str(&"name").unwrap_or_else(|_| key.clone());
+        let is_system = uobj.get_bool(&"is_system").unwrap_or(false);
+        let uid = parse_u32_field(&uobj, "uid", &format!("users.{}.uid", key))?;
+
+        let main_group = uobj.get_str(&"main_group").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("users.{}.main_group", key),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+
+        let extra_groups = match uobj.get_arr(&"extra_groups") {
+            Ok(groups) => Some(
+                groups
+                    .vec_ref()
+                    .iter()
+                    .map(|item| item.get_str())
+                    .collect::<Result<Vec<String>, _>>()
+                    .map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                        field_name: format!("users.{}.extra_groups", key),
+                        problem: COULD_NOT_BE_PARSED_AS_ARR,
+                    })?,
+            ),
+            Err(_) => None,
+        };
+
+        let full_name = uobj.get_str(&"full_name").ok();
+
+        let shell_raw = uobj.get_str(&"shell").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("users.{}.shell", key),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+        let shell = parse_user_shell_ref(&shell_raw);
+
+        let install_missing_shell = uobj.get_bool(&"install_missing_shell").unwrap_or(false);
+
+        let password = match uobj.get_obj(&"password") {
+            Ok(password) => Some(parse_securable_input(&password, &format!("users.{}.password", key))?),
+            Err(_) => None,
+        };
+
+        let state_stub = uobj.get_bool(&"state_stub").unwrap_or(false);
+        let match_by = parse_match_by(&uobj, &format!("users.{}.match_by", key))?;
+        let not_matched_error_behavior = parse_ignorable_error_behavior(
+            &uobj,
+            "not_matched_error_behavior",
+            &format!("users.{}.not_matched_error_behavior", key),
+        )?;
+        let prune_on_removal = uobj.get_bool(&"prune_on_removal").unwrap_or(false);
+
+        result.insert(
+            key.clone(),
+            User {
+                name,
+                is_system,
+                uid,
+                main_group,
+                extra_groups,
+                full_name,
+                shell,
+                install_missing_shell,
+                password,
+                state_stub,
+                match_by,
+                not_matched_error_behavior,
+                prune_on_removal,
+            },
+        );
+    }
+
+    Ok(Some(result))
+}
+
+// `groups` follows the same keying convention as `users`.
+fn parse_groups(config: &Obj) -> Result<Option<HashMap<String, Group>>, SchemaParsingError> {
+    let groups = match config.get_obj(&"groups") {
+        Ok(groups) => groups,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = HashMap::with_capacity(groups.len());
+
+    for (key, graw) in groups.iter() {
+        let gobj = graw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("groups.{}", key),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let name = gobj.get_str(&"name").unwrap_or_else(|_| key.clone());
+        let is_system = gobj.get_bool(&"is_system").unwrap_or(false);
+        let gid = parse_u32_field(&gobj, "gid", &format!("groups.{}.gid", key))?;
+        let state_stub = gobj.get_bool(&"state_stub").unwrap_or(false);
+        let match_by = parse_match_by(&gobj, &format!("groups.{}.match_by", key))?;
+        let not_matched_error_behavior = parse_ignorable_error_behavior(
+            &gobj,
+            "not_matched_error_behavior",
+            &format!("groups.{}.not_matched_error_behavior", key),
+        )?;
+        let prune_on_removal = gobj.get_bool(&"prune_on_removal").unwrap_or(false);
+
+        result.insert(
+            key.clone(),
+            Group {
+                name,
+                is_system,
+                gid,
+                state_stub,
+                match_by,
+                not_matched_error_behavior,
+                prune_on_removal,
+            },
+        );
+    }
+
+    Ok(Some(result))
+}
+
+// `shells` follows the same keying convention as `users`/`groups`: the key is the shell's bin
+// name (`bash`, `zsh`, ...).
+fn parse_shells(config: &Obj) -> Result<Option<HashMap<String, Shell>>, SchemaParsingError> {
+    let shells = match config.get_obj(&"shells") {
+        Ok(shells) => shells,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = HashMap::with_capacity(shells.len());
+
+    for (key, sraw) in shells.iter() {
+        let sobj = sraw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("shells.{}", key),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let install_completion = sobj.get_bool(&"install_completion").unwrap_or(false);
+        let install_completion_error_behavior = match sobj.get_str(&"install_completion_error_behavior") {
+            Ok(raw) => match raw.to_lowercase().as_str() {
+                "error" => ErrorBehavior::Error,
+                "warn" => ErrorBehavior::Warn,
+                _ => {
+                    return Err(SchemaParsingError::MalformedOofInstruction {
+                        field_name: format!("shells.{}.install_completion_error_behavior", key),
+                        problem: UNKNOWN_ERROR_BEHAVIOR,
+                    });
+                }
+            },
+            Err(_) => ErrorBehavior::Warn,
+        };
+        let system_config_file = sobj.get_str(&"system_config_file").ok().map(PathBuf::from);
+
+        result.insert(
+            key.clone(),
+            Shell {
+                install_completion,
+                install_completion_error_behavior,
+                system_config_file,
+            },
+        );
+    }
+
+    Ok(Some(result))
+}
+
+// Top-level `privesc`, distinct from `target.ssh.privesc`, which is prefixed to every command run
+// on an SSH target. The target backends in `crate::target` do not read this top-level value.
+fn parse_top_level_privesc(config: &Obj) -> Result<Option<Privesc>, SchemaParsingError> {
+    match config.get_obj(&"privesc") {
+        Ok(privesc) => Ok(Some(parse_privesc(&privesc)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_rawpkgs(config: &Obj) -> Result<Option<Vec<String>>, SchemaParsingError> {
+    match config.get_arr(&"rawpkgs") {
+        Ok(rawpkgs) => {
+            let names: Result<Vec<String>, _> = rawpkgs.vec_ref().iter().map(|item| item.get_str()).collect();
+            names.map(Some).map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                field_name: "rawpkgs".to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_ARR,
+            })
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_intentpkgs(config: &Obj) -> Result<Option<Vec<IntentPkg>>, SchemaParsingError> {
+    let pkgs = match config.get_arr(&"intentpkgs") {
+        Ok(pkgs) => pkgs,
+        Err(_) => return Ok(None),
+    };
+
+    let mut result = Vec::with_capacity(pkgs.len());
+
+    for (idx, praw) in pkgs.iter().enumerate() {
+        let pobj = praw.get_obj().map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("intentpkgs[{}]", idx),
+            problem: COULD_NOT_BE_PARSED_AS_OBJ,
+        })?;
+
+        let name = pobj.get_str(&"name").map_err(|_| SchemaParsingError::MalformedOofInstruction {
+            field_name: format!("intentpkgs[{}].name", idx),
+            problem: COULD_NOT_BE_PARSED_AS_STRING,
+        })?;
+
+        let opts = match pobj.get_obj(&"opts") {
+            Ok(opts_obj) => {
+                let mut opts = HashMap::with_capacity(opts_obj.len());
+                for (opt_name, value) in opts_obj.iter() {
+                    let parsed = parse_intentpkg_opt(value, &format!("intentpkgs[{}].opts.{}", idx, opt_name))?;
+                    opts.insert(opt_name.clone(), parsed);
+                }
+                Some(opts)
+            }
+            Err(_) => None,
+        };
+
+        result.push(IntentPkg { name, opts });
+    }
+
+    Ok(Some(result))
+}
+
+// `IntentPkgOpt::List` holds a single boxed value rather than a `Vec`, so it can only stand in
+// for a one-element `.over` array; anything longer is rejected outright rather than silently
+// dropped, since a schema-parsing layer has no business deciding which of an option's values some
+// future consumer didn't need.
+fn parse_intentpkg_opt(value: &Value, field_name: &str) -> Result<IntentPkgOpt, SchemaParsingError> {
+    if value.is_null() {
+        return Ok(IntentPkgOpt::Null);
+    }
+
+    if let Ok(b) = value.get_bool() {
+        return Ok(IntentPkgOpt::Boolean(b));
+    }
+
+    if let Ok(i) = value.get_int() {
+        return i
+            .to_string()
+            .parse::<isize>()
+            .map(IntentPkgOpt::Number)
+            .map_err(|_| SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: COULD_NOT_BE_PARSED_AS_INT,
+            });
+    }
+
+    if let Ok(s) = value.get_str() {
+        return Ok(IntentPkgOpt::String(s));
+    }
+
+    if let Ok(arr) = value.get_arr() {
+        let items = arr.vec_ref();
+        if items.len() > 1 {
+            return Err(SchemaParsingError::MalformedOofInstruction {
+                field_name: field_name.to_string(),
+                problem: INTENTPKG_OPT_LIST_MUST_HAVE_EXACTLY_ONE_ELEMENT,
+            });
+        }
+
+        let only = items.first().cloned().unwrap_or(Value::Null);
+        return Ok(IntentPkgOpt::List(Box::new(parse_intentpkg_opt(&only, field_name)?)));
+    }
+
+    if let Ok(obj) = value.get_obj() {
+        let mut map = HashMap::with_capacity(obj.len());
+        for (key, inner) in obj.iter() {
+            let parsed = parse_intentpkg_opt(inner, &format!("{}.{}", field_name, key))?;
+            map.insert(key.clone(), Box::new(parsed));
+        }
+        return Ok(IntentPkgOpt::Map(map));
+    }
+
+    Err(SchemaParsingError::MalformedOofInstruction {
+        field_name: field_name.to_string(),
+        problem: COULD_NOT_BE_PARSED_AS_INTENTPKG_OPT,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_schema_version_matches_exact_registered_version() {
+        let result = select_schema_version("system", "2021.08.01", SYSTEM_SCHEMA_VERSIONS);
+        assert!(matches!(result, Ok(OofFileSchema::System20210801)));
+    }
+
+    #[test]
+    fn select_schema_version_matches_the_documented_range_example() {
+        let result = select_schema_version("system", ">=2021.08.01, <2022", SYSTEM_SCHEMA_VERSIONS);
+        assert!(matches!(result, Ok(OofFileSchema::System20210801)));
+    }
+
+    #[test]
+    fn select_schema_version_rejects_a_range_none_of_the_registry_satisfies() {
+        let result = select_schema_version("system", ">=2022.01.01", SYSTEM_SCHEMA_VERSIONS);
+        assert!(matches!(result, Err(SchemaParsingError::UnsupportedSchemaVersion { .. })));
+    }
+
+    #[test]
+    fn select_schema_version_rejects_an_unparseable_requirement() {
+        let result = select_schema_version("system", "not a version", SYSTEM_SCHEMA_VERSIONS);
+        assert!(matches!(result, Err(SchemaParsingError::UnsupportedSchemaVersion { .. })));
+    }
+
+    #[test]
+    fn normalize_version_req_strips_leading_zeros_from_every_component() {
+        assert_eq!(normalize_version_req(">=2021.08.01, <2022"), ">=2021.8.1, <2022");
+    }
+
+    #[test]
+    fn normalize_version_req_leaves_a_bare_zero_component_alone() {
+        assert_eq!(normalize_version_req(">=2021.0.0"), ">=2021.0.0");
+    }
+}
@@ -0,0 +1,228 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Command;
+
+use console::style;
+
+use crate::schemas::system::{IgnorableErrorBehavior, SchemaParsingError, SecurableInput};
+
+// Holds resolved secret material and overwrites it with zeroes when dropped, so decrypted/typed
+// values don't linger in memory for longer than the run needs them.
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    fn from_string(value: String) -> Self {
+        Secret(value.into_bytes())
+    }
+
+    fn from_bytes(value: Vec<u8>) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn expose_str(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.0)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            // SAFETY: `self.0` is a valid, owned `Vec<u8>`; a volatile write can't be elided by
+            // the optimizer the way a plain assignment could be right before the buffer is freed.
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(REDACTED)")
+    }
+}
+
+#[derive(Debug)]
+pub enum SecretError {
+    FileReadFailed { path: String, problem: String },
+    GpgFailed { path: String, problem: String },
+    PromptFailed { field: String, problem: String },
+    NoTty { field: String },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretError::FileReadFailed { path, problem } => {
+                write!(f, "could not read {}: {}", path, problem)
+            }
+            SecretError::GpgFailed { path, problem } => {
+                write!(f, "gpg could not decrypt {}: {}", path, problem)
+            }
+            SecretError::PromptFailed { field, problem } => {
+                write!(f, "could not prompt for {}: {}", field, problem)
+            }
+            SecretError::NoTty { field } => {
+                write!(f, "{} requires a prompt, but no TTY is attached", field)
+            }
+        }
+    }
+}
+
+// Resolves `SecurableInput`s into `Secret`s. `PromptOnce` answers are cached for the resolver's
+// lifetime (one run), keyed on the prompt's `group` name, so every field that names the same
+// group is only prompted once, no matter how many distinct fields reference it.
+pub struct SecretResolver {
+    gpg_executable: Option<String>,
+    once_cache: RefCell<HashMap<String, Secret>>,
+}
+
+impl SecretResolver {
+    pub fn new(gpg_executable: Option<String>) -> Self {
+        SecretResolver {
+            gpg_executable,
+            once_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn resolve(&self, input: &SecurableInput, field_name: &str) -> Result<Secret, SecretError> {
+        match input {
+            SecurableInput::Raw(value) => Ok(Secret::from_string(value.clone())),
+            SecurableInput::FilePlaintext(path) => read_plaintext_file(path),
+            SecurableInput::FileGpgNear { path, key, executable } => {
+                self.decrypt_gpg(path, key, executable.as_deref())
+            }
+            SecurableInput::PromptOnce { group } => self.resolve_prompt_once(group, field_name),
+            SecurableInput::PromptAlways => {
+                Ok(Secret::from_string(prompt_for_secret(field_name)?))
+            }
+        }
+    }
+
+    fn resolve_prompt_once(&self, group: &str, field_name: &str) -> Result<Secret, SecretError> {
+        if let Some(cached) = self.once_cache.borrow().get(group) {
+            return Ok(Secret::from_bytes(cached.expose().to_vec()));
+        }
+
+        let entered = Secret::from_string(prompt_for_secret(field_name)?);
+        let returned = Secret::from_bytes(entered.expose().to_vec());
+        self.once_cache.borrow_mut().insert(group.to_string(), entered);
+        Ok(returned)
+    }
+
+    fn decrypt_gpg(&self, path: &str, key: &str, executable: Option<&str>) -> Result<Secret, SecretError> {
+        let gpg = executable
+            .map(String::from)
+            .or_else(|| self.gpg_executable.clone())
+            .unwrap_or_else(|| "gpg".to_string());
+
+        let output = Command::new(&gpg)
+            .arg("--batch")
+            .arg("--quiet")
+            .arg("--decrypt")
+            .arg("--local-user")
+            .arg(key)
+            .arg(path)
+            .output()
+            .map_err(|err| SecretError::GpgFailed { path: path.to_string(), problem: err.to_string() })?;
+
+        if !output.status.success() {
+            return Err(SecretError::GpgFailed {
+                path: path.to_string(),
+                problem: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        Ok(Secret::from_bytes(output.stdout))
+    }
+}
+
+fn read_plaintext_file(path: &str) -> Result<Secret, SecretError> {
+    std::fs::read(path)
+        .map(Secret::from_bytes)
+        .map_err(|err| SecretError::FileReadFailed { path: path.to_string(), problem: err.to_string() })
+}
+
+fn prompt_for_secret(field_name: &str) -> Result<String, SecretError> {
+    if !console::user_attended() {
+        return Err(SecretError::NoTty { field: field_name.to_string() });
+    }
+
+    let term = console::Term::stdout();
+    term.write_str(&format!("{} ({}): ", style("secret").cyan(), field_name))
+        .map_err(|err| SecretError::PromptFailed { field: field_name.to_string(), problem: err.to_string() })?;
+
+    term.read_secure_line()
+        .map_err(|err| SecretError::PromptFailed { field: field_name.to_string(), problem: err.to_string() })
+}
+
+// Resolves a secret, routing failures through the same `IgnorableErrorBehavior` every other
+// skippable per-item operation in this crate uses: `Error` aborts the run, `Warn` logs and
+// carries on without the secret, `Ignore` carries on silently.
+pub fn resolve_or_handle(
+    resolver: &SecretResolver,
+    input: &SecurableInput,
+    field_name: &str,
+    behavior: &IgnorableErrorBehavior,
+) -> Result<Option<Secret>, SchemaParsingError> {
+    match resolver.resolve(input, field_name) {
+        Ok(secret) => Ok(Some(secret)),
+        Err(err) => match behavior {
+            IgnorableErrorBehavior::Error => Err(SchemaParsingError::SecretResolutionFailed {
+                field: field_name.to_string(),
+                problem: err.to_string(),
+            }),
+            IgnorableErrorBehavior::Warn => {
+                eprintln!("{} resolving {}: {}", style("warning").yellow(), field_name, err);
+                Ok(None)
+            }
+            IgnorableErrorBehavior::Ignore => Ok(None),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_prompt_once_returns_a_cached_value_without_prompting() {
+        let resolver = SecretResolver::new(None);
+        resolver.once_cache.borrow_mut().insert("alice-pw".to_string(), Secret::from_string("hunter2".to_string()));
+
+        // No TTY is attached in a test run, so if this fell through to prompt_for_secret it would
+        // return Err(NoTty) instead of the cached value.
+        let secret = resolver.resolve_prompt_once("alice-pw", "users.alice.password").unwrap();
+        assert_eq!(secret.expose_str().unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn resolve_prompt_once_keys_the_cache_on_the_group_name_not_which_field_asked() {
+        let resolver = SecretResolver::new(None);
+        resolver.once_cache.borrow_mut().insert("shared".to_string(), Secret::from_string("s3cr3t".to_string()));
+
+        let via_field_a = resolver.resolve_prompt_once("shared", "users.alice.password").unwrap();
+        let via_field_b = resolver.resolve_prompt_once("shared", "users.bob.password").unwrap();
+
+        assert_eq!(via_field_a.expose_str().unwrap(), "s3cr3t");
+        assert_eq!(via_field_b.expose_str().unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn resolve_prompt_once_does_not_share_cache_entries_across_distinct_groups() {
+        let resolver = SecretResolver::new(None);
+        resolver.once_cache.borrow_mut().insert("alice-pw".to_string(), Secret::from_string("hunter2".to_string()));
+
+        // "bob-pw" was never seeded, so a real miss falls through to prompt_for_secret, which
+        // fails immediately since no TTY is attached in a test run.
+        let result = resolver.resolve_prompt_once("bob-pw", "users.bob.password");
+        assert!(matches!(result, Err(SecretError::NoTty { .. })));
+    }
+}
@@ -0,0 +1,429 @@
+// This file is part of the OOF project, released under the Creative Commons CC0
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::schemas::system::{
+    Disk, DiskType, Group, PrivEscMethod, Privesc, Target, TargetType, User,
+};
+use crate::secrets::Secret;
+
+#[derive(Debug)]
+pub enum TargetError {
+    CommandFailed { backend: &'static str, problem: String },
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetError::CommandFailed { backend, problem } => {
+                write!(f, "{} target failed: {}", backend, problem)
+            }
+        }
+    }
+}
+
+// Materializes a resolved `SystemSchema20210801` against wherever `target` points: the same
+// disk/user/group/package operations run unchanged whether that's the local machine, a remote
+// host over SSH, a chroot, or a mounted disk image.
+pub trait TargetBackend {
+    fn name(&self) -> &'static str;
+    fn ensure_disks(&self, disks: &[Disk]) -> Result<(), TargetError>;
+    fn ensure_groups(&self, groups: &HashMap<String, Group>) -> Result<(), TargetError>;
+    // `passwords` holds a resolved Secret per username (keyed to match `User.name`, not the
+    // document's map key), for any user whose `password` field resolved to something. Piped to
+    // `chpasswd` over stdin rather than passed as an argument, so a secret never shows up in a
+    // process listing.
+    fn ensure_users(
+        &self,
+        users: &HashMap<String, User>,
+        passwords: &HashMap<String, Secret>,
+    ) -> Result<(), TargetError>;
+    fn install_packages(&self, artifacts: &[PathBuf]) -> Result<(), TargetError>;
+}
+
+pub fn backend_for(target: &Target) -> Result<Box<dyn TargetBackend>, TargetError> {
+    match &target.target_type {
+        TargetType::TargetSelf => Ok(Box::new(GenericTargetBackend {
+            executor: SelfExecutor,
+            label: "self",
+        })),
+        TargetType::Ssh { host, port, user, identity_file, privesc } => {
+            Ok(Box::new(GenericTargetBackend {
+                executor: SshExecutor {
+                    host: host.clone(),
+                    port: *port,
+                    user: user.clone(),
+                    identity_file: identity_file.clone(),
+                    privesc: privesc.clone(),
+                },
+                label: "ssh",
+            }))
+        }
+        TargetType::Chroot { mountpoint } => Ok(Box::new(GenericTargetBackend {
+            executor: ChrootExecutor { mountpoint: mountpoint.clone() },
+            label: "chroot",
+        })),
+        TargetType::Image { path } => Ok(Box::new(ImageBackend::mount(path)?)),
+    }
+}
+
+// Builds the `Command` a `GenericTargetBackend` should run a given program+args under. Each
+// target type differs only in how a command actually gets executed, not in which commands get
+// run, so that's the one thing backends vary on.
+trait Executor {
+    fn command(&self, program: &str, args: &[&str]) -> Command;
+}
+
+struct SelfExecutor;
+
+impl Executor for SelfExecutor {
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    }
+}
+
+struct SshExecutor {
+    host: String,
+    port: u16,
+    user: String,
+    identity_file: Option<PathBuf>,
+    privesc: Option<Privesc>,
+}
+
+impl Executor for SshExecutor {
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut command = Command::new("ssh");
+        command.arg("-p").arg(self.port.to_string());
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        command.arg(format!("{}@{}", self.user, self.host));
+        command.arg(remote_shell_command(program, args, self.privesc.as_ref()));
+        command
+    }
+}
+
+fn remote_shell_command(program: &str, args: &[&str], privesc: Option<&Privesc>) -> String {
+    let mut parts = Vec::with_capacity(args.len() + 2);
+
+    if let Some(privesc) = privesc {
+        parts.push(match privesc.method {
+            PrivEscMethod::Doas => "doas".to_string(),
+            PrivEscMethod::Sudo => "sudo".to_string(),
+        });
+    }
+
+    parts.push(shell_quote(program));
+    parts.extend(args.iter().map(|arg| shell_quote(arg)));
+
+    parts.join(" ")
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+struct ChrootExecutor {
+    mountpoint: PathBuf,
+}
+
+impl Executor for ChrootExecutor {
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut command = Command::new("chroot");
+        command.arg(&self.mountpoint).arg(program).args(args);
+        command
+    }
+}
+
+struct GenericTargetBackend<E: Executor> {
+    executor: E,
+    label: &'static str,
+}
+
+impl<E: Executor> GenericTargetBackend<E> {
+    fn run(&self, program: &str, args: &[&str]) -> Result<(), TargetError> {
+        match self.executor.command(program, args).status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(TargetError::CommandFailed {
+                backend: self.label,
+                problem: format!("{} exited with {}", program, status),
+            }),
+            Err(err) => Err(TargetError::CommandFailed {
+                backend: self.label,
+                problem: err.to_string(),
+            }),
+        }
+    }
+
+    fn run_with_stdin(&self, program: &str, args: &[&str], stdin_data: &[u8]) -> Result<(), TargetError> {
+        use std::io::Write;
+        use std::process::Stdio;
+
+        let mut command = self.executor.command(program, args);
+        command.stdin(Stdio::piped());
+
+        let mut child = command.spawn().map_err(|err| TargetError::CommandFailed {
+            backend: self.label,
+            problem: err.to_string(),
+        })?;
+
+        child
+            .stdin
+            .take()
+            .expect("stdin was just requested as piped")
+            .write_all(stdin_data)
+            .map_err(|err| TargetError::CommandFailed {
+                backend: self.label,
+                problem: err.to_string(),
+            })?;
+
+        match child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(TargetError::CommandFailed {
+                backend: self.label,
+                problem: format!("{} exited with {}", program, status),
+            }),
+            Err(err) => Err(TargetError::CommandFailed {
+                backend: self.label,
+                problem: err.to_string(),
+            }),
+        }
+    }
+}
+
+impl<E: Executor> TargetBackend for GenericTargetBackend<E> {
+    fn name(&self) -> &'static str {
+        self.label
+    }
+
+    fn ensure_disks(&self, disks: &[Disk]) -> Result<(), TargetError> {
+        for disk in disks {
+            self.run("mount", &["-t", disk_type_flag(&disk.disk_type), &disk.source, &disk.mountpoint])?;
+        }
+        Ok(())
+    }
+
+    fn ensure_groups(&self, groups: &HashMap<String, Group>) -> Result<(), TargetError> {
+        for group in groups.values() {
+            let mut args = vec!["-f".to_string()];
+            if group.is_system {
+                args.push("-r".to_string());
+            }
+            if let Some(gid) = group.gid {
+                args.push("-g".to_string());
+                args.push(gid.to_string());
+            }
+            args.push(group.name.clone());
+
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            self.run("groupadd", &args)?;
+        }
+        Ok(())
+    }
+
+    fn ensure_users(
+        &self,
+        users: &HashMap<String, User>,
+        passwords: &HashMap<String, Secret>,
+    ) -> Result<(), TargetError> {
+        for user in users.values() {
+            let mut args = vec!["-g".to_string(), user.main_group.clone()];
+            if user.is_system {
+                args.push("-r".to_string());
+            }
+            if let Some(uid) = user.uid {
+                args.push("-u".to_string());
+                args.push(uid.to_string());
+            }
+            args.push(user.name.clone());
+
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            self.run("useradd", &args)?;
+
+            if let Some(password) = passwords.get(&user.name) {
+                let line = format!(
+                    "{}:{}\n",
+                    user.name,
+                    password.expose_str().map_err(|err| TargetError::CommandFailed {
+                        backend: self.label,
+                        problem: format!("password for {} is not valid UTF-8: {}", user.name, err),
+                    })?
+                );
+                self.run_with_stdin("chpasswd", &[], line.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn install_packages(&self, artifacts: &[PathBuf]) -> Result<(), TargetError> {
+        if artifacts.is_empty() {
+            return Ok(());
+        }
+
+        let paths: Vec<String> = artifacts.iter().map(|path| path.to_string_lossy().into_owned()).collect();
+        let mut args = vec!["--noconfirm".to_string(), "-U".to_string()];
+        args.extend(paths);
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.run("pacman", &args)
+    }
+}
+
+fn disk_type_flag(disk_type: &DiskType) -> &'static str {
+    match disk_type {
+        DiskType::Bcachefs => "bcachefs",
+        DiskType::Btrfs => "btrfs",
+        DiskType::Ext2 => "ext2",
+        DiskType::Ext3 => "ext3",
+        DiskType::Ext4 => "ext4",
+        DiskType::Jfs => "jfs",
+        DiskType::Nilfs2 => "nilfs2",
+        DiskType::Ntfs => "ntfs3",
+        DiskType::Swap => "swap",
+        DiskType::Tmpfs => "tmpfs",
+        DiskType::Vfat => "vfat",
+        DiskType::Xfs => "xfs",
+        DiskType::Zfs => "zfs",
+    }
+}
+
+// Mounts a disk image via a loopback device and delegates to a `ChrootExecutor` underneath;
+// the loop device and mountpoint are torn back down when the backend is dropped.
+pub struct ImageBackend {
+    inner: GenericTargetBackend<ChrootExecutor>,
+    loop_device: String,
+    mountpoint: PathBuf,
+}
+
+impl ImageBackend {
+    pub fn mount(image: &Path) -> Result<Self, TargetError> {
+        let loop_device = attach_loop_device(image)?;
+        let mountpoint = std::env::temp_dir().join(format!("oof-image-{}", next_id()));
+
+        std::fs::create_dir_all(&mountpoint).map_err(|err| TargetError::CommandFailed {
+            backend: "image",
+            problem: err.to_string(),
+        })?;
+
+        let mountpoint_str = mountpoint.to_string_lossy().into_owned();
+        run_simple("mount", &[loop_device.as_str(), mountpoint_str.as_str()])?;
+
+        Ok(ImageBackend {
+            inner: GenericTargetBackend {
+                executor: ChrootExecutor { mountpoint: mountpoint.clone() },
+                label: "image",
+            },
+            loop_device,
+            mountpoint,
+        })
+    }
+}
+
+impl TargetBackend for ImageBackend {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn ensure_disks(&self, disks: &[Disk]) -> Result<(), TargetError> {
+        self.inner.ensure_disks(disks)
+    }
+
+    fn ensure_groups(&self, groups: &HashMap<String, Group>) -> Result<(), TargetError> {
+        self.inner.ensure_groups(groups)
+    }
+
+    fn ensure_users(
+        &self,
+        users: &HashMap<String, User>,
+        passwords: &HashMap<String, Secret>,
+    ) -> Result<(), TargetError> {
+        self.inner.ensure_users(users, passwords)
+    }
+
+    fn install_packages(&self, artifacts: &[PathBuf]) -> Result<(), TargetError> {
+        self.inner.install_packages(artifacts)
+    }
+}
+
+impl Drop for ImageBackend {
+    fn drop(&mut self) {
+        let _ = Command::new("umount").arg(&self.mountpoint).status();
+        let _ = Command::new("losetup").arg("-d").arg(&self.loop_device).status();
+    }
+}
+
+fn attach_loop_device(image: &Path) -> Result<String, TargetError> {
+    let output = Command::new("losetup")
+        .arg("--find")
+        .arg("--show")
+        .arg(image)
+        .output()
+        .map_err(|err| TargetError::CommandFailed { backend: "image", problem: err.to_string() })?;
+
+    if !output.status.success() {
+        return Err(TargetError::CommandFailed {
+            backend: "image",
+            problem: format!("losetup exited with {}", output.status),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn run_simple(program: &str, args: &[&str]) -> Result<(), TargetError> {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(TargetError::CommandFailed {
+            backend: "image",
+            problem: format!("{} exited with {}", program, status),
+        }),
+        Err(err) => Err(TargetError::CommandFailed { backend: "image", problem: err.to_string() }),
+    }
+}
+
+fn next_id() -> usize {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_quote_wraps_a_plain_argument_in_single_quotes() {
+        assert_eq!(shell_quote("hello"), "'hello'");
+    }
+
+    #[test]
+    fn shell_quote_escapes_an_embedded_single_quote() {
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn remote_shell_command_quotes_program_and_args_with_no_privesc() {
+        assert_eq!(remote_shell_command("useradd", &["-m", "alice"], None), "'useradd' '-m' 'alice'");
+    }
+
+    #[test]
+    fn remote_shell_command_prepends_sudo_when_privesc_is_sudo() {
+        let privesc = Privesc { method: PrivEscMethod::Sudo, config_file: None };
+        assert_eq!(
+            remote_shell_command("useradd", &["-m", "alice"], Some(&privesc)),
+            "sudo 'useradd' '-m' 'alice'"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_prepends_doas_when_privesc_is_doas() {
+        let privesc = Privesc { method: PrivEscMethod::Doas, config_file: None };
+        assert_eq!(remote_shell_command("whoami", &[], Some(&privesc)), "doas 'whoami'");
+    }
+}